@@ -0,0 +1,77 @@
+//! Tool approval - let a person confirm tool calls before they run
+
+use crate::ToolCall;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+/// Observation the model gets for a call that was not approved
+pub const TOOL_DENIED: &str = "Tool denied by user";
+
+/// Approver trait
+///
+/// Asked before every call of a tool registered with
+/// `ReActAgent::with_approval_required`; the run waits for the answer.
+#[async_trait]
+pub trait ToolApprover: Send + Sync {
+    async fn approve(&self, call: &ToolCall) -> bool;
+}
+
+/// Approver that waits for answers delivered by call id
+///
+/// Used where approvals arrive out of band, e.g. as WebSocket messages.
+/// A pending call whose queue is dropped is denied.
+#[derive(Default)]
+pub struct ApprovalQueue {
+    pending: Mutex<HashMap<String, oneshot::Sender<bool>>>,
+}
+
+impl ApprovalQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the pending call `call_id`; false if no such call is waiting
+    pub fn resolve(&self, call_id: &str, approved: bool) -> bool {
+        let sender = self.pending.lock().unwrap().remove(call_id);
+        sender.is_some_and(|sender| sender.send(approved).is_ok())
+    }
+}
+
+#[async_trait]
+impl ToolApprover for ApprovalQueue {
+    async fn approve(&self, call: &ToolCall) -> bool {
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(call.id.clone(), sender);
+        receiver.await.unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_approval_queue_resolves_by_call_id() {
+        let queue = Arc::new(ApprovalQueue::new());
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "delete".to_string(),
+            arguments: serde_json::json!({}),
+        };
+
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.approve(&call).await }
+        });
+        while !queue.pending.lock().unwrap().contains_key("call_1") {
+            tokio::task::yield_now().await;
+        }
+        assert!(!queue.resolve("call_2", true));
+        assert!(queue.resolve("call_1", true));
+        assert!(waiting.await.unwrap());
+        assert!(!queue.resolve("call_1", false));
+    }
+}
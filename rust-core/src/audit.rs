@@ -0,0 +1,185 @@
+//! Audit logging - keep what was sent to and received from providers
+
+use crate::events::JsonlWriter;
+use crate::{AgentError, HeaderRedactor, HttpRequest, HttpResponse, HttpTransport};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// One provider call as it went over the wire
+///
+/// Bodies are the exact bytes sent and received, decoded as UTF-8 (provider
+/// APIs speak JSON; invalid bytes would be replaced). Headers are passed
+/// through `HeaderRedactor`, so API keys are never recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Milliseconds since the Unix epoch when the request was sent
+    pub timestamp_ms: u64,
+    pub method: String,
+    pub url: String,
+    pub request_headers: BTreeMap<String, String>,
+    pub request_body: String,
+    /// Absent when no response arrived
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub response_headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub response_body: String,
+    /// Transport failure, when no response arrived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Audit sink trait
+///
+/// Like `EventSink::emit`, `record` runs on the request path and must not
+/// block: hand the record to a queue or background task and return.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: AuditRecord);
+}
+
+/// Audit sink that appends one JSON object per line to a file
+///
+/// Records are written by a background thread; dropping the sink writes
+/// out everything still queued.
+pub struct JsonlAuditSink {
+    writer: JsonlWriter<AuditRecord>,
+}
+
+impl JsonlAuditSink {
+    /// Append to `path`, creating it if needed
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        Ok(Self {
+            writer: JsonlWriter::create(path)?,
+        })
+    }
+}
+
+impl AuditSink for JsonlAuditSink {
+    fn record(&self, record: AuditRecord) {
+        self.writer.write(record);
+    }
+}
+
+/// Transport wrapper that reports every exchange to an `AuditSink`
+///
+/// Wrap a provider's transport with it (`with_transport`) to audit that
+/// provider. Recording costs a copy of the bodies; the writing happens off
+/// the request path.
+pub struct AuditTransport {
+    inner: Arc<dyn HttpTransport>,
+    sink: Arc<dyn AuditSink>,
+    redactor: HeaderRedactor,
+}
+
+impl AuditTransport {
+    pub fn new(inner: Arc<dyn HttpTransport>, sink: Arc<dyn AuditSink>) -> Self {
+        Self {
+            inner,
+            sink,
+            redactor: HeaderRedactor::default(),
+        }
+    }
+
+    /// Redact headers with `redactor` instead of the default policy
+    ///
+    /// Requests sent through this transport are also logged with it.
+    pub fn with_redactor(mut self, redactor: HeaderRedactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+}
+
+#[async_trait]
+impl HttpTransport for AuditTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AgentError> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let mut record = AuditRecord {
+            timestamp_ms,
+            method: request.method.to_string(),
+            url: request.url.clone(),
+            request_headers: self.redactor.redact(&request.headers),
+            request_body: String::from_utf8_lossy(&request.body).into_owned(),
+            status: None,
+            response_headers: BTreeMap::new(),
+            response_body: String::new(),
+            error: None,
+            duration_ms: 0,
+        };
+
+        let start = Instant::now();
+        let result = self.inner.send(request).await;
+        record.duration_ms = start.elapsed().as_millis() as u64;
+        match &result {
+            Ok(response) => {
+                record.status = Some(response.status);
+                record.response_headers = self.redactor.redact(&response.headers);
+                record.response_body = String::from_utf8_lossy(&response.body).into_owned();
+            }
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        self.sink.record(record);
+        result
+    }
+
+    fn redactor(&self) -> &HeaderRedactor {
+        &self.redactor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentRequest, LLMProvider, MockTransport, OpenAIProvider, REDACTED};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct CollectingAudit {
+        records: Mutex<Vec<AuditRecord>>,
+    }
+
+    impl AuditSink for CollectingAudit {
+        fn record(&self, record: AuditRecord) {
+            self.records.lock().unwrap().push(record);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_transport_records_redacted_exchange() {
+        let reply = serde_json::json!({
+            "choices": [{"message": {"role": "assistant", "content": "Hi"}}]
+        });
+        let sink = Arc::new(CollectingAudit::default());
+        let transport = AuditTransport::new(
+            Arc::new(MockTransport::new().with_json(200, reply.clone())),
+            sink.clone(),
+        );
+        let provider =
+            OpenAIProvider::new("sk-secret".to_string()).with_transport(Arc::new(transport));
+        provider
+            .chat(AgentRequest {
+                task: "Hello".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let records = sink.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].method, "POST");
+        assert_eq!(records[0].request_headers["authorization"], REDACTED);
+        assert!(records[0].request_body.contains("Hello"));
+        assert_eq!(records[0].status, Some(200));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&records[0].response_body).unwrap(),
+            reply
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! Cancellation - stop long-running jobs between units of work
+
+use crate::AgentError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag a long-running job checks between units of work
+///
+/// Clones share the flag, so one clone can be kept to `cancel` (e.g. from
+/// a shutdown handler) while another is passed to the job. Work already in
+/// progress is not interrupted.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// `AgentError::Cancelled` with `context` if cancelled
+    pub(crate) fn check(&self, context: impl FnOnce() -> String) -> Result<(), AgentError> {
+        if self.is_cancelled() {
+            Err(AgentError::Cancelled(context()))
+        } else {
+            Ok(())
+        }
+    }
+}
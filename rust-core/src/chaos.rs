@@ -0,0 +1,202 @@
+//! Chaos provider - fault injection for resilience testing
+
+use crate::{AgentError, AgentRequest, AgentResponse, LLMProvider};
+use async_trait::async_trait;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Kind of failure injected by the chaos provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosFault {
+    /// Fails with `AgentError::ApiError`
+    Api,
+    /// Fails with `AgentError::ParseError`
+    Parse,
+}
+
+impl ChaosFault {
+    fn into_error(self) -> AgentError {
+        match self {
+            ChaosFault::Api => AgentError::ApiError("chaos: injected API failure".to_string()),
+            ChaosFault::Parse => {
+                AgentError::ParseError("chaos: injected parse failure".to_string())
+            }
+        }
+    }
+}
+
+/// Provider wrapper that injects latency and failures
+///
+/// Every call sleeps for the configured latency (plus random jitter), then
+/// fails with probability `failure_rate` using a fault drawn from the
+/// weighted fault distribution, or forwards to the wrapped provider. The
+/// random source is seeded, so the same seed replays the same sequence.
+pub struct ChaosProvider {
+    inner: Box<dyn LLMProvider>,
+    failure_rate: f64,
+    faults: Vec<(ChaosFault, u32)>,
+    latency: Duration,
+    jitter: Duration,
+    rng: Mutex<StdRng>,
+}
+
+impl ChaosProvider {
+    pub fn new(inner: Box<dyn LLMProvider>) -> Self {
+        Self {
+            inner,
+            failure_rate: 0.0,
+            faults: Vec::new(),
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            rng: Mutex::new(StdRng::seed_from_u64(0)),
+        }
+    }
+
+    /// Probability in `[0.0, 1.0]` that a call fails
+    pub fn with_failure_rate(mut self, failure_rate: f64) -> Self {
+        self.failure_rate = failure_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Add a fault kind with a relative weight; defaults to `ChaosFault::Api` only
+    pub fn with_fault(mut self, fault: ChaosFault, weight: u32) -> Self {
+        self.faults.push((fault, weight));
+        self
+    }
+
+    /// Fixed latency added before every call
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Upper bound of the random latency added on top of `with_latency`
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// Sleep for the drawn delay, then fail if a fault was drawn
+    async fn inject(&self) -> Result<(), AgentError> {
+        let (delay, fault) = self.roll();
+
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        match fault {
+            Some(fault) => Err(fault.into_error()),
+            None => Ok(()),
+        }
+    }
+
+    /// Draw the delay and outcome of the next call
+    fn roll(&self) -> (Duration, Option<ChaosFault>) {
+        let mut rng = self.rng.lock().unwrap();
+
+        let mut delay = self.latency;
+        if !self.jitter.is_zero() {
+            delay += self.jitter.mul_f64(rng.gen::<f64>());
+        }
+
+        if !rng.gen_bool(self.failure_rate) {
+            return (delay, None);
+        }
+
+        let fault = match WeightedIndex::new(self.faults.iter().map(|(_, weight)| *weight)) {
+            Ok(index) => self.faults[index.sample(&mut *rng)].0,
+            Err(_) => ChaosFault::Api,
+        };
+        (delay, Some(fault))
+    }
+}
+
+#[async_trait]
+impl LLMProvider for ChaosProvider {
+    async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+        self.inject().await?;
+        self.inner.chat(request).await
+    }
+
+    async fn chat_stream(
+        &self,
+        request: AgentRequest,
+        deltas: UnboundedSender<String>,
+    ) -> Result<AgentResponse, AgentError> {
+        self.inject().await?;
+        self.inner.chat_stream(request, deltas).await
+    }
+
+    async fn validate(&self) -> Result<(), AgentError> {
+        self.inner.validate().await
+    }
+
+    async fn init(&self) -> Result<(), AgentError> {
+        self.inner.init().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockProvider;
+
+    fn request() -> AgentRequest {
+        AgentRequest {
+            task: "Hello".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn chaos(seed: u64) -> ChaosProvider {
+        ChaosProvider::new(Box::new(MockProvider::new()))
+            .with_failure_rate(0.5)
+            .with_fault(ChaosFault::Api, 1)
+            .with_fault(ChaosFault::Parse, 1)
+            .with_seed(seed)
+    }
+
+    async fn outcomes(provider: &ChaosProvider, calls: usize) -> Vec<String> {
+        let mut outcomes = Vec::new();
+        for _ in 0..calls {
+            outcomes.push(match provider.chat(request()).await {
+                Ok(_) => "ok".to_string(),
+                Err(e) => e.to_string(),
+            });
+        }
+        outcomes
+    }
+
+    #[tokio::test]
+    async fn test_chaos_provider_is_reproducible() {
+        let first = outcomes(&chaos(42), 32).await;
+        let second = outcomes(&chaos(42), 32).await;
+        assert_eq!(first, second);
+        assert!(first.iter().any(|o| o == "ok"));
+        assert!(first.iter().any(|o| o.starts_with("API error")));
+        assert!(first.iter().any(|o| o.starts_with("Parse error")));
+    }
+
+    #[tokio::test]
+    async fn test_chaos_provider_failure_rate_bounds() {
+        let inner = || Box::new(MockProvider::new());
+
+        let healthy = ChaosProvider::new(inner());
+        assert!(healthy.chat(request()).await.is_ok());
+
+        let broken = ChaosProvider::new(inner()).with_failure_rate(1.0);
+        assert!(matches!(
+            broken.chat(request()).await,
+            Err(AgentError::ApiError(_))
+        ));
+    }
+}
@@ -0,0 +1,44 @@
+//! Text chunking - split long text into pieces sized for the vector store
+
+/// Split `text` into chunks of at most `max_chars` characters
+///
+/// Chunks break between words and whitespace runs collapse to single
+/// spaces. A single word longer than `max_chars` becomes its own chunk.
+pub(crate) fn chunk_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_chars = 0;
+
+    for word in text.split_whitespace() {
+        let word_chars = word.chars().count();
+        if current_chars > 0 && current_chars + 1 + word_chars > max_chars {
+            chunks.push(std::mem::take(&mut current));
+            current_chars = 0;
+        }
+        if current_chars > 0 {
+            current.push(' ');
+            current_chars += 1;
+        }
+        current.push_str(word);
+        current_chars += word_chars;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_text() {
+        assert_eq!(
+            chunk_text("one two  three\nfour", 9),
+            ["one two", "three", "four"]
+        );
+        assert_eq!(chunk_text("abcdefghijk xy", 5), ["abcdefghijk", "xy"]);
+        assert!(chunk_text("   ", 5).is_empty());
+    }
+}
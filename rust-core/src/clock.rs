@@ -0,0 +1,71 @@
+//! Clock - the agent's time source, replaceable in tests
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Clock trait
+///
+/// The agent measures `duration_ms` and `ttft_ms` with its clock, and
+/// `MemoryVectorStore` ages documents for its recency boost by the clock's
+/// wall time. Timeouts still use tokio's timer.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, for comparing against stored timestamps
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Real monotonic time; the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves when told to, for exact timing assertions
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    wall: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self::at(SystemTime::now())
+    }
+
+    /// Mock clock whose wall time starts at `wall`
+    pub fn at(wall: SystemTime) -> Self {
+        Self {
+            start: Instant::now(),
+            wall,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.wall + *self.elapsed.lock().unwrap()
+    }
+}
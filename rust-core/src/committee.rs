@@ -0,0 +1,162 @@
+//! Committee provider - several models answer, a judge merges their answers
+
+use crate::{AgentError, AgentRequest, AgentResponse, LLMProvider, Thought};
+use async_trait::async_trait;
+
+/// Default instruction sent to the judge ahead of the task and answers
+pub const DEFAULT_JUDGE_PROMPT: &str = "Several assistants answered the task below. \
+Combine them into the single best answer, resolving disagreements on the merits. End with a \
+line \"Confidence: high\", \"Confidence: medium\" or \"Confidence: low\" and a short reason, \
+based on how well the answers agree.";
+
+/// Provider that asks every member concurrently and has a judge merge the answers
+///
+/// The judge's response is returned, with each member's answer prepended
+/// to its thoughts (type `committee_answer`). Failing members become
+/// warnings; the call only fails if every member or the judge fails.
+/// Meant for final answers: tool calls requested by members are dropped.
+pub struct CommitteeProvider {
+    members: Vec<Box<dyn LLMProvider>>,
+    judge: Box<dyn LLMProvider>,
+    judge_prompt: String,
+}
+
+impl CommitteeProvider {
+    pub fn new(members: Vec<Box<dyn LLMProvider>>, judge: Box<dyn LLMProvider>) -> Self {
+        Self {
+            members,
+            judge,
+            judge_prompt: DEFAULT_JUDGE_PROMPT.to_string(),
+        }
+    }
+
+    /// Replace `DEFAULT_JUDGE_PROMPT`; the task and the numbered answers
+    /// are appended to it
+    pub fn with_judge_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.judge_prompt = prompt.into();
+        self
+    }
+}
+
+#[async_trait]
+impl LLMProvider for CommitteeProvider {
+    async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+        let start = std::time::Instant::now();
+        let outcomes = futures::future::join_all(
+            self.members
+                .iter()
+                .map(|member| member.chat(request.clone())),
+        )
+        .await;
+
+        let mut answers = Vec::new();
+        let mut warnings = Vec::new();
+        let mut last_error = None;
+        for (i, outcome) in outcomes.into_iter().enumerate() {
+            match outcome {
+                Ok(response) => {
+                    if !response.tool_calls.is_empty() {
+                        warnings.push(format!(
+                            "committee member {} asked for tools; its tool calls were dropped",
+                            i + 1
+                        ));
+                    }
+                    answers.push(response.result);
+                }
+                Err(e) => {
+                    warnings.push(format!("committee member {} failed: {}", i + 1, e));
+                    last_error = Some(e);
+                }
+            }
+        }
+        if answers.is_empty() {
+            return Err(last_error
+                .unwrap_or_else(|| AgentError::ApiError("committee has no members".to_string())));
+        }
+
+        let mut task = format!("{}\n\nTask: {}", self.judge_prompt, request.task);
+        for (i, answer) in answers.iter().enumerate() {
+            task.push_str(&format!("\n\nAnswer {}:\n{}", i + 1, answer));
+        }
+        let mut response = self
+            .judge
+            .chat(AgentRequest {
+                task,
+                headers: request.headers.clone(),
+                locale: request.locale.clone(),
+                ..Default::default()
+            })
+            .await?;
+
+        let mut thoughts: Vec<Thought> = answers
+            .into_iter()
+            .enumerate()
+            .map(|(i, answer)| Thought {
+                thought_type: "committee_answer".to_string(),
+                content: format!("Answer {}: {}", i + 1, answer),
+            })
+            .collect();
+        thoughts.append(&mut response.thoughts);
+        response.thoughts = thoughts;
+        warnings.append(&mut response.warnings);
+        response.warnings = warnings;
+        response.duration_ms = start.elapsed().as_millis() as u64;
+        Ok(response)
+    }
+
+    async fn validate(&self) -> Result<(), AgentError> {
+        for member in &self.members {
+            member.validate().await?;
+        }
+        self.judge.validate().await
+    }
+
+    async fn init(&self) -> Result<(), AgentError> {
+        for member in &self.members {
+            member.init().await?;
+        }
+        self.judge.init().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaosProvider, MockProvider};
+
+    fn answering(result: &str) -> Box<dyn LLMProvider> {
+        Box::new(MockProvider::new().with_response(AgentResponse {
+            result: result.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_judge_merges_member_answers() {
+        let committee = CommitteeProvider::new(
+            vec![
+                answering("Paris"),
+                answering("Paris, France"),
+                Box::new(ChaosProvider::new(Box::new(MockProvider::new())).with_failure_rate(1.0)),
+            ],
+            Box::new(MockProvider::new()),
+        )
+        .with_judge_prompt("Merge these.");
+
+        let response = committee
+            .chat(AgentRequest {
+                task: "Capital of France?".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // The mock judge echoes its prompt
+        assert!(response
+            .result
+            .starts_with("Mock response for: Merge these."));
+        assert!(response.result.contains("Answer 2:\nParis, France"));
+        assert_eq!(response.thoughts[0].thought_type, "committee_answer");
+        assert_eq!(response.thoughts[1].content, "Answer 2: Paris, France");
+        assert_eq!(response.warnings.len(), 1);
+    }
+}
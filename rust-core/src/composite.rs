@@ -0,0 +1,132 @@
+//! Composite vector store - search several stores as one
+
+use crate::{AgentError, CancellationToken, VectorStore};
+
+/// Vector store that fans searches out to several backing stores
+///
+/// Every store is searched for the full `limit`, the hits are merged and
+/// re-ranked by score, and the global top `limit` is returned. Scores must
+/// be comparable across stores for the ranking to be meaningful. Writes go
+/// to the primary store only.
+pub struct CompositeVectorStore {
+    primary: Box<dyn VectorStore>,
+    secondaries: Vec<Box<dyn VectorStore>>,
+}
+
+impl CompositeVectorStore {
+    pub fn new(primary: Box<dyn VectorStore>) -> Self {
+        Self {
+            primary,
+            secondaries: Vec::new(),
+        }
+    }
+
+    /// Add a read-only store to the search fan-out
+    pub fn with_store(mut self, store: Box<dyn VectorStore>) -> Self {
+        self.secondaries.push(store);
+        self
+    }
+
+    fn stores(&self) -> impl Iterator<Item = &dyn VectorStore> {
+        std::iter::once(self.primary.as_ref()).chain(self.secondaries.iter().map(|s| s.as_ref()))
+    }
+}
+
+impl VectorStore for CompositeVectorStore {
+    fn add(&self, text: String, metadata: serde_json::Value) -> Result<(), AgentError> {
+        self.primary.add(text, metadata)
+    }
+
+    fn search(&self, query: String, limit: usize) -> Result<Vec<(String, f32)>, AgentError> {
+        let mut results = Vec::new();
+        for store in self.stores() {
+            results.extend(store.search(query.clone(), limit)?);
+        }
+
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    fn search_with_embeddings(
+        &self,
+        query: String,
+        limit: usize,
+    ) -> Result<Vec<(String, f32, Vec<f32>)>, AgentError> {
+        let mut results = Vec::new();
+        for store in self.stores() {
+            results.extend(store.search_with_embeddings(query.clone(), limit)?);
+        }
+
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    fn add_batch(
+        &self,
+        documents: Vec<(String, serde_json::Value)>,
+        cancel: &CancellationToken,
+    ) -> Result<usize, AgentError> {
+        self.primary.add_batch(documents, cancel)
+    }
+
+    fn document_id(&self, text: &str) -> String {
+        self.primary.document_id(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Store returning fixed hits and recording what was added
+    struct FixedStore {
+        hits: Vec<(String, f32)>,
+        added: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FixedStore {
+        fn new(hits: &[(&str, f32)]) -> Self {
+            Self {
+                hits: hits.iter().map(|(t, s)| (t.to_string(), *s)).collect(),
+                added: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    impl VectorStore for FixedStore {
+        fn add(&self, text: String, _metadata: serde_json::Value) -> Result<(), AgentError> {
+            self.added.lock().unwrap().push(text);
+            Ok(())
+        }
+
+        fn search(&self, _query: String, limit: usize) -> Result<Vec<(String, f32)>, AgentError> {
+            Ok(self.hits.iter().take(limit).cloned().collect())
+        }
+    }
+
+    #[test]
+    fn test_composite_merges_by_score() {
+        let hot = FixedStore::new(&[("hot-a", 0.9), ("hot-b", 0.4)]);
+        let cold = FixedStore::new(&[("cold-a", 0.7), ("cold-b", 0.6)]);
+        let store = CompositeVectorStore::new(Box::new(hot)).with_store(Box::new(cold));
+
+        let results = store.search("query".to_string(), 3).unwrap();
+        let texts: Vec<_> = results.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(texts, ["hot-a", "cold-a", "cold-b"]);
+    }
+
+    #[test]
+    fn test_composite_adds_to_primary() {
+        let hot = FixedStore::new(&[]);
+        let cold = FixedStore::new(&[]);
+        let (hot_added, cold_added) = (hot.added.clone(), cold.added.clone());
+        let store = CompositeVectorStore::new(Box::new(hot)).with_store(Box::new(cold));
+
+        store.add("doc".to_string(), serde_json::json!({})).unwrap();
+        assert_eq!(*hot_added.lock().unwrap(), ["doc"]);
+        assert!(cold_added.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,57 @@
+//! Retrieved context - fit vector store hits into the prompt budget
+
+/// Retrieved chunks split by whether they fit the budget
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PackedContext {
+    pub used: Vec<String>,
+    pub dropped: Vec<String>,
+}
+
+/// Take chunks in rank order while the total stays within `budget_chars`
+///
+/// A chunk that does not fit is dropped but smaller chunks after it may
+/// still be used.
+pub(crate) fn pack(chunks: Vec<String>, budget_chars: usize) -> PackedContext {
+    let mut packed = PackedContext::default();
+    let mut remaining = budget_chars;
+    for chunk in chunks {
+        let len = chunk.chars().count();
+        if len <= remaining {
+            remaining -= len;
+            packed.used.push(chunk);
+        } else {
+            packed.dropped.push(chunk);
+        }
+    }
+    packed
+}
+
+/// Prompt section listing the used chunks, empty when there are none
+pub(crate) fn render(used: &[String]) -> String {
+    if used.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("Context:\n");
+    for chunk in used {
+        out.push_str("- ");
+        out.push_str(chunk);
+        out.push('\n');
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_within_budget() {
+        let chunks = vec!["aaaa".to_string(), "bbbbbb".to_string(), "cc".to_string()];
+        let packed = pack(chunks, 7);
+        assert_eq!(packed.used, ["aaaa", "cc"]);
+        assert_eq!(packed.dropped, ["bbbbbb"]);
+        assert_eq!(render(&packed.used), "Context:\n- aaaa\n- cc\n\n");
+        assert_eq!(render(&[]), "");
+    }
+}
@@ -0,0 +1,274 @@
+//! Embedders - turn text into vectors for the vector stores
+
+use crate::chunking::chunk_text;
+use crate::{AgentError, CancellationToken};
+
+/// Embedder trait
+pub trait Embedder: Send + Sync {
+    /// Length of every vector this embedder returns
+    fn dimensions(&self) -> usize;
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentError>;
+
+    /// Every vector is already unit length (as from most hosted embedding
+    /// APIs)
+    ///
+    /// `MemoryVectorStore::with_embedder` then scores with a dot product
+    /// right away. Stores still check each vector, so a wrong `true` costs
+    /// speed, not correct scores. The default is `false`.
+    fn normalizes(&self) -> bool {
+        false
+    }
+
+    /// Embed `texts` in order, stopping with `AgentError::Cancelled` once
+    /// `cancel` is set
+    ///
+    /// The default embeds one text at a time and checks between them;
+    /// embedders calling a batch API can override it and check per request.
+    fn embed_batch(
+        &self,
+        texts: &[&str],
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Vec<f32>>, AgentError> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for text in texts {
+            cancel.check(|| format!("embedded {} of {} texts", vectors.len(), texts.len()))?;
+            vectors.push(self.embed(text)?);
+        }
+        Ok(vectors)
+    }
+}
+
+/// Local bag-of-words embedder using feature hashing
+///
+/// Each lowercase alphanumeric token is hashed into one of `dimensions`
+/// buckets with a hash-derived sign. It needs no model or network access,
+/// which makes it a reasonable default for tests and small corpora, but it
+/// only captures lexical overlap, not meaning.
+#[derive(Debug, Clone)]
+pub struct HashEmbedder {
+    dimensions: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+        }
+    }
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl Embedder for HashEmbedder {
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentError> {
+        let mut vector = vec![0.0; self.dimensions];
+        for token in tokenize(text) {
+            let hash = fnv1a(token.as_bytes());
+            let sign = if hash & (1 << 63) == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dimensions as u64) as usize] += sign;
+        }
+        Ok(vector)
+    }
+}
+
+/// What `TruncatingEmbedder` does with inputs over its limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TruncationStrategy {
+    /// Fail with `AgentError::Validation`
+    Error,
+    /// Drop the start and embed the last `max_chars` characters
+    TruncateHead,
+    /// Drop the end and embed the first `max_chars` characters; the default,
+    /// matching what most embedding APIs do silently
+    #[default]
+    TruncateTail,
+    /// Embed every `max_chars` chunk and average the vectors, weighted by
+    /// chunk length; costs one embedding call per chunk
+    SplitAndAverage,
+}
+
+/// Embedder wrapper that keeps inputs within the model's limit
+///
+/// Limits are in characters because no tokenizer is available here; pick
+/// a `max_chars` safely under the model's token limit (English averages
+/// about four characters per token). Inputs within the limit pass through
+/// unchanged.
+pub struct TruncatingEmbedder {
+    inner: Box<dyn Embedder>,
+    max_chars: usize,
+    strategy: TruncationStrategy,
+}
+
+impl TruncatingEmbedder {
+    pub fn new(inner: Box<dyn Embedder>, max_chars: usize) -> Self {
+        Self {
+            inner,
+            max_chars: max_chars.max(1),
+            strategy: TruncationStrategy::default(),
+        }
+    }
+
+    pub fn with_strategy(mut self, strategy: TruncationStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+}
+
+impl Embedder for TruncatingEmbedder {
+    fn dimensions(&self) -> usize {
+        self.inner.dimensions()
+    }
+
+    /// Averaged chunk vectors are shorter than unit length
+    fn normalizes(&self) -> bool {
+        self.inner.normalizes() && self.strategy != TruncationStrategy::SplitAndAverage
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentError> {
+        let len = text.chars().count();
+        if len <= self.max_chars {
+            return self.inner.embed(text);
+        }
+        match self.strategy {
+            TruncationStrategy::Error => Err(AgentError::Validation(format!(
+                "embedding input is {} characters, over the limit of {}",
+                len, self.max_chars
+            ))),
+            TruncationStrategy::TruncateHead => {
+                let tail: String = text.chars().skip(len - self.max_chars).collect();
+                self.inner.embed(&tail)
+            }
+            TruncationStrategy::TruncateTail => {
+                let head: String = text.chars().take(self.max_chars).collect();
+                self.inner.embed(&head)
+            }
+            TruncationStrategy::SplitAndAverage => {
+                let mut sum = vec![0.0; self.dimensions()];
+                let mut total = 0.0;
+                for chunk in chunk_text(text, self.max_chars) {
+                    let weight = chunk.chars().count() as f32;
+                    for (acc, x) in sum.iter_mut().zip(self.inner.embed(&chunk)?) {
+                        *acc += x * weight;
+                    }
+                    total += weight;
+                }
+                Ok(sum.into_iter().map(|x| x / total).collect())
+            }
+        }
+    }
+}
+
+/// Lowercase alphanumeric tokens of `text`
+pub(crate) fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+}
+
+/// 64-bit FNV-1a, stable across platforms and releases
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Cosine similarity; zero when either vector has no magnitude
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Dot product; the cosine similarity of unit vectors
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Scale `v` to unit length in place; zero vectors and vectors already
+/// unit length (up to float error) are left as they are
+pub(crate) fn l2_normalize(v: &mut [f32]) {
+    let squared = v.iter().map(|x| x * x).sum::<f32>();
+    if squared > 0.0 && (squared - 1.0).abs() > 1e-6 {
+        let norm = squared.sqrt();
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_embedder_is_deterministic() {
+        let embedder = HashEmbedder::new(64);
+        let a = embedder.embed("Rust is fast").unwrap();
+        let b = embedder.embed("rust, is FAST!").unwrap();
+        assert_eq!(a.len(), 64);
+        assert_eq!(a, b);
+        assert!((cosine_similarity(&a, &b) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&a, &vec![0.0; 64]), 0.0);
+    }
+
+    #[test]
+    fn test_embed_batch_stops_when_cancelled() {
+        let embedder = HashEmbedder::new(8);
+        let cancel = CancellationToken::new();
+        assert_eq!(embedder.embed_batch(&["a", "b"], &cancel).unwrap().len(), 2);
+
+        cancel.cancel();
+        assert!(matches!(
+            embedder.embed_batch(&["a", "b"], &cancel),
+            Err(AgentError::Cancelled(_))
+        ));
+    }
+
+    #[test]
+    fn test_truncation_strategies() {
+        let text = "alpha beta gamma delta";
+        let embed = |strategy| {
+            TruncatingEmbedder::new(Box::new(HashEmbedder::new(64)), 11)
+                .with_strategy(strategy)
+                .embed(text)
+        };
+        let hash = |text| HashEmbedder::new(64).embed(text).unwrap();
+
+        assert!(matches!(
+            embed(TruncationStrategy::Error),
+            Err(AgentError::Validation(_))
+        ));
+        assert_eq!(
+            embed(TruncationStrategy::TruncateTail).unwrap(),
+            hash("alpha beta")
+        );
+        assert_eq!(
+            embed(TruncationStrategy::TruncateHead).unwrap(),
+            hash("gamma delta")
+        );
+
+        // "alpha beta" and "gamma delta" weighted 10:11
+        let averaged = embed(TruncationStrategy::SplitAndAverage).unwrap();
+        let expected: Vec<f32> = hash("alpha beta")
+            .iter()
+            .zip(hash("gamma delta"))
+            .map(|(a, b)| (a * 10.0 + b * 11.0) / 21.0)
+            .collect();
+        assert_eq!(averaged, expected);
+
+        let short = TruncatingEmbedder::new(Box::new(HashEmbedder::new(64)), 100);
+        assert_eq!(short.embed(text).unwrap(), hash(text));
+    }
+}
@@ -0,0 +1,164 @@
+//! Retrieval evaluation - recall, precision and MRR over labeled queries
+
+use crate::{AgentError, VectorStore};
+use serde::{Deserialize, Serialize};
+
+/// Query with the documents a good search should return
+///
+/// `relevant` lists document ids, as returned by
+/// `MemoryVectorStore::upsert` (see `VectorStore::document_id`), or
+/// document texts exactly as they were added. Ids make duplicate texts, and
+/// texts differing only in whitespace, count as one document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LabeledQuery {
+    pub query: String,
+    pub relevant: Vec<String>,
+}
+
+/// Scores of one query's top `k` hits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryReport {
+    pub query: String,
+    /// Share of the relevant documents among the hits
+    pub recall: f32,
+    /// Share of the hits that are relevant
+    pub precision: f32,
+    /// 1 / rank of the first relevant hit, 0 when there is none
+    pub reciprocal_rank: f32,
+}
+
+/// Per-query scores and their means
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalReport {
+    pub k: usize,
+    pub queries: Vec<QueryReport>,
+    /// Mean recall@k
+    pub recall: f32,
+    /// Mean precision@k
+    pub precision: f32,
+    /// Mean reciprocal rank
+    pub mrr: f32,
+}
+
+/// Search `store` with every labeled query and score the top `k` hits
+///
+/// Precision divides by `k` even when the store returns fewer hits, so
+/// small stores are not flattered. Queries without relevant documents
+/// count as recall 1.
+pub fn evaluate_retrieval(
+    store: &dyn VectorStore,
+    examples: &[LabeledQuery],
+    k: usize,
+) -> Result<RetrievalReport, AgentError> {
+    let mut queries = Vec::with_capacity(examples.len());
+    for example in examples {
+        let hits = store.search(example.query.clone(), k)?;
+        // Index of the labeled entry each hit matches, by id or text
+        let matches: Vec<Option<usize>> = hits
+            .iter()
+            .map(|(text, _)| {
+                let id = store.document_id(text);
+                example
+                    .relevant
+                    .iter()
+                    .position(|label| *label == id || label == text)
+            })
+            .collect();
+        let relevant: Vec<bool> = matches.iter().map(Option::is_some).collect();
+        let hit_count = relevant.iter().filter(|&&hit| hit).count();
+        // A labeled document found twice is still one document
+        let mut found: Vec<usize> = matches.into_iter().flatten().collect();
+        found.sort_unstable();
+        found.dedup();
+        let found = found.len();
+
+        queries.push(QueryReport {
+            query: example.query.clone(),
+            recall: if example.relevant.is_empty() {
+                1.0
+            } else {
+                found as f32 / example.relevant.len() as f32
+            },
+            precision: if k == 0 {
+                0.0
+            } else {
+                hit_count as f32 / k as f32
+            },
+            reciprocal_rank: relevant
+                .iter()
+                .position(|&hit| hit)
+                .map_or(0.0, |i| 1.0 / (i + 1) as f32),
+        });
+    }
+
+    let mean = |score: fn(&QueryReport) -> f32| {
+        if queries.is_empty() {
+            0.0
+        } else {
+            queries.iter().map(score).sum::<f32>() / queries.len() as f32
+        }
+    };
+    Ok(RetrievalReport {
+        k,
+        recall: mean(|q| q.recall),
+        precision: mean(|q| q.precision),
+        mrr: mean(|q| q.reciprocal_rank),
+        queries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryVectorStore;
+
+    #[test]
+    fn test_evaluate_retrieval_scores() {
+        let store = MemoryVectorStore::new();
+        for doc in ["rust ownership", "rust borrowing", "python typing"] {
+            store.add(doc.to_string(), serde_json::json!({})).unwrap();
+        }
+        let examples = [
+            LabeledQuery {
+                query: "python typing".to_string(),
+                relevant: vec!["python typing".to_string()],
+            },
+            LabeledQuery {
+                query: "rust ownership".to_string(),
+                relevant: vec!["rust ownership".to_string(), "missing".to_string()],
+            },
+        ];
+
+        let report = evaluate_retrieval(&store, &examples, 2).unwrap();
+        assert_eq!(report.queries[0].recall, 1.0);
+        assert_eq!(report.queries[0].precision, 0.5);
+        assert_eq!(report.queries[0].reciprocal_rank, 1.0);
+        assert_eq!(report.queries[1].recall, 0.5);
+        assert_eq!(report.recall, 0.75);
+        assert_eq!(report.mrr, 1.0);
+    }
+
+    #[test]
+    fn test_evaluate_retrieval_matches_ids() {
+        let store = MemoryVectorStore::new().with_id_namespace("docs");
+        let id = store
+            .upsert("rust ownership".to_string(), serde_json::json!({}))
+            .unwrap();
+        // The same document ingested again from another source
+        store
+            .add("rust  ownership".to_string(), serde_json::json!({}))
+            .unwrap();
+        store
+            .add("python typing".to_string(), serde_json::json!({}))
+            .unwrap();
+        let examples = [LabeledQuery {
+            query: "rust ownership".to_string(),
+            relevant: vec![id],
+        }];
+
+        let report = evaluate_retrieval(&store, &examples, 2).unwrap();
+        assert_eq!(report.queries[0].recall, 1.0);
+        assert_eq!(report.queries[0].precision, 1.0);
+        assert_eq!(report.mrr, 1.0);
+    }
+}
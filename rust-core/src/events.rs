@@ -0,0 +1,190 @@
+//! Structured agent events for observability pipelines
+
+use crate::AgentError;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Something the agent did, as emitted to an `EventSink`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentEvent {
+    /// Identifies the run; every event of one run shares it
+    pub run_id: u64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub kind: AgentEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEventKind {
+    /// A run started for `task`
+    RequestReceived { task: String },
+    /// Step `step` (starting at 1) is calling the model
+    Step { step: usize },
+    /// The model asked for a tool call
+    ToolCall {
+        tool: String,
+        arguments: serde_json::Value,
+    },
+    /// The run finished
+    Response {
+        duration_ms: u64,
+        thoughts: usize,
+        warnings: usize,
+        timed_out: bool,
+    },
+    /// The run paused after `step` steps and can be resumed
+    Paused { step: usize },
+    /// The run failed
+    Failed { error: String },
+}
+
+impl AgentEvent {
+    pub(crate) fn new(run_id: u64, kind: AgentEventKind) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            run_id,
+            timestamp_ms,
+            kind,
+        }
+    }
+}
+
+/// Id for a new run, unique within the process
+pub(crate) fn next_run_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Sink trait
+///
+/// `emit` is called on the agent's hot path, so implementations must not
+/// block: hand the event to a queue or background task and return.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: AgentEvent);
+}
+
+/// Sink that appends one JSON object per line to a file
+///
+/// Events are queued and written by a background thread. Dropping the sink
+/// writes out everything still queued.
+pub struct JsonlSink {
+    writer: JsonlWriter<AgentEvent>,
+}
+
+impl JsonlSink {
+    /// Append to `path`, creating it if needed
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        Ok(Self {
+            writer: JsonlWriter::create(path)?,
+        })
+    }
+}
+
+impl EventSink for JsonlSink {
+    fn emit(&self, event: AgentEvent) {
+        self.writer.write(event);
+    }
+}
+
+/// Background JSON-lines file writer shared by the file-backed sinks
+pub(crate) struct JsonlWriter<T> {
+    sender: Option<Sender<T>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<T: Serialize + Send + 'static> JsonlWriter<T> {
+    pub(crate) fn create(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let file = File::options().create(true).append(true).open(path)?;
+        let (sender, receiver) = mpsc::channel::<T>();
+
+        let worker = std::thread::spawn(move || {
+            let mut out = BufWriter::new(file);
+            while let Ok(item) = receiver.recv() {
+                write_line(&mut out, &item);
+                // Write batches out as soon as the queue runs dry
+                while let Ok(item) = receiver.try_recv() {
+                    write_line(&mut out, &item);
+                }
+                if let Err(e) = out.flush() {
+                    tracing::warn!(error = %e, "failed to flush JSON lines");
+                }
+            }
+        });
+
+        Ok(Self {
+            sender: Some(sender),
+            worker: Some(worker),
+        })
+    }
+
+    /// Queue `item`; never blocks
+    pub(crate) fn write(&self, item: T) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(item);
+        }
+    }
+}
+
+fn write_line(out: &mut impl Write, item: &impl Serialize) {
+    let result = serde_json::to_writer(&mut *out, item)
+        .map_err(std::io::Error::from)
+        .and_then(|()| out.write_all(b"\n"));
+    if let Err(e) = result {
+        tracing::warn!(error = %e, "failed to write JSON line");
+    }
+}
+
+impl<T> Drop for JsonlWriter<T> {
+    fn drop(&mut self) {
+        // Closing the channel stops the worker once the queue is written
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jsonl_sink_writes_one_event_per_line() {
+        let path =
+            std::env::temp_dir().join(format!("agent-core-events-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let sink = JsonlSink::create(&path).unwrap();
+        sink.emit(AgentEvent::new(
+            7,
+            AgentEventKind::RequestReceived {
+                task: "Hello".to_string(),
+            },
+        ));
+        sink.emit(AgentEvent::new(7, AgentEventKind::Step { step: 1 }));
+        drop(sink);
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["type"], "request_received");
+        assert_eq!(lines[0]["task"], "Hello");
+        assert_eq!(lines[1]["run_id"], 7);
+        assert_eq!(lines[1]["step"], 1);
+    }
+}
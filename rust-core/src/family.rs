@@ -0,0 +1,657 @@
+//! Model families - per-API request building quirks
+
+use crate::responses::{
+    decode, AnthropicMessageResponse, GeminiGenerateResponse, OpenAIChatResponse,
+};
+use crate::{AgentError, AgentRequest, AgentResponse, ToolCall, ToolResult, ToolSpec};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Default `max_tokens` for families that require one
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Author of a message
+///
+/// Serialized as the lowercase wire name; unknown names fail to
+/// deserialize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl Role {
+    /// Wire name, as used by OpenAI-style APIs
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Chat message in provider-neutral form
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+    /// Tools an assistant message asked to call
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Result a tool message carries; `content` is its text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_result: Option<ToolResult>,
+}
+
+impl Message {
+    fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_result: None,
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub fn tool(content: impl Into<String>) -> Self {
+        Self::new(Role::Tool, content)
+    }
+
+    /// Assistant turn asking for `calls`, with any reasoning as `content`
+    pub fn tool_calls(content: impl Into<String>, calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls: calls,
+            ..Self::assistant(content)
+        }
+    }
+
+    /// Tool turn answering one tool call
+    pub fn tool_result(result: ToolResult) -> Self {
+        Self {
+            tool_result: Some(result.clone()),
+            ..Self::tool(result.content)
+        }
+    }
+}
+
+/// Request-building quirks shared by every model of one API family
+///
+/// A family decides how tools are described, where system messages go and
+/// which role names the API accepts. Supporting a new API means adding a
+/// family rather than branching inside each provider.
+pub trait ModelFamily: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Wire name of a neutral role
+    fn role_name(&self, role: Role) -> &'static str {
+        role.as_str()
+    }
+
+    /// Wire format of one tool definition
+    fn format_tool(&self, tool: &ToolSpec) -> Value;
+
+    /// Wire format of one message, including tool calls and results
+    ///
+    /// The default is the OpenAI shape: assistant `tool_calls` with
+    /// JSON-encoded arguments, and results as `tool` messages naming their
+    /// `tool_call_id`.
+    fn format_message(&self, message: &Message) -> Value {
+        let mut wire = json!({"role": self.role_name(message.role), "content": message.content});
+        if !message.tool_calls.is_empty() {
+            wire["tool_calls"] = message
+                .tool_calls
+                .iter()
+                .map(|call| {
+                    // Arguments the model failed to encode are kept as raw strings
+                    let arguments = match &call.arguments {
+                        Value::String(raw) => raw.clone(),
+                        arguments => arguments.to_string(),
+                    };
+                    json!({
+                        "id": call.id,
+                        "type": "function",
+                        "function": {"name": call.name, "arguments": arguments},
+                    })
+                })
+                .collect();
+        }
+        if let Some(result) = &message.tool_result {
+            wire["tool_call_id"] = json!(result.call_id);
+        }
+        wire
+    }
+
+    /// Build the chat request body
+    ///
+    /// Families whose API has a safety configuration map
+    /// `request.safety_settings` onto it; the others ignore the field.
+    fn build_request(&self, model: &str, messages: &[Message], request: &AgentRequest) -> Value;
+
+    /// Convert the API's response body into an `AgentResponse`
+    fn parse_response(&self, body: &Value) -> Result<AgentResponse, AgentError>;
+}
+
+/// OpenAI chat completions format
+pub struct OpenAIFamily;
+
+impl ModelFamily for OpenAIFamily {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn format_tool(&self, tool: &ToolSpec) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": tool.parameters,
+            },
+        })
+    }
+
+    fn build_request(&self, model: &str, messages: &[Message], request: &AgentRequest) -> Value {
+        let messages: Vec<Value> = messages.iter().map(|m| self.format_message(m)).collect();
+
+        let mut body = json!({"model": model, "messages": messages});
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if !request.tools.is_empty() {
+            body["tools"] = request.tools.iter().map(|t| self.format_tool(t)).collect();
+        }
+        body
+    }
+
+    fn parse_response(&self, body: &Value) -> Result<AgentResponse, AgentError> {
+        decode::<OpenAIChatResponse>(body)?.try_into()
+    }
+}
+
+/// Anthropic messages format
+///
+/// System messages move to the top-level `system` field. Tool calls are
+/// `tool_use` content blocks, and tool results are `tool_result` blocks in
+/// a `user` turn; consecutive results share one turn.
+pub struct AnthropicFamily;
+
+impl ModelFamily for AnthropicFamily {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn role_name(&self, role: Role) -> &'static str {
+        match role {
+            Role::Tool => "user",
+            other => other.as_str(),
+        }
+    }
+
+    fn format_tool(&self, tool: &ToolSpec) -> Value {
+        json!({
+            "name": tool.name,
+            "description": tool.description,
+            "input_schema": tool.parameters,
+        })
+    }
+
+    fn format_message(&self, message: &Message) -> Value {
+        let role = self.role_name(message.role);
+        if let Some(result) = &message.tool_result {
+            return json!({"role": role, "content": [{
+                "type": "tool_result",
+                "tool_use_id": result.call_id,
+                "content": result.content,
+                "is_error": result.is_error,
+            }]});
+        }
+        if message.tool_calls.is_empty() {
+            return json!({"role": role, "content": message.content});
+        }
+        let mut blocks = Vec::new();
+        if !message.content.is_empty() {
+            blocks.push(json!({"type": "text", "text": message.content}));
+        }
+        for call in &message.tool_calls {
+            blocks.push(json!({
+                "type": "tool_use",
+                "id": call.id,
+                "name": call.name,
+                "input": call.arguments,
+            }));
+        }
+        json!({"role": role, "content": blocks})
+    }
+
+    fn build_request(&self, model: &str, messages: &[Message], request: &AgentRequest) -> Value {
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| m.content.as_str())
+            .collect();
+        let mut turns: Vec<Value> = Vec::new();
+        for message in messages.iter().filter(|m| m.role != Role::System) {
+            let wire = self.format_message(message);
+            // Results of one step's tool calls go back in a single user turn
+            if message.tool_result.is_some() {
+                if let Some(Value::Array(blocks)) = turns
+                    .last_mut()
+                    .filter(|last| last["content"][0]["type"] == "tool_result")
+                    .map(|last| &mut last["content"])
+                {
+                    blocks.push(wire["content"][0].clone());
+                    continue;
+                }
+            }
+            turns.push(wire);
+        }
+
+        let mut body = json!({
+            "model": model,
+            "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            "messages": turns,
+        });
+        if !system.is_empty() {
+            body["system"] = json!(system.join("\n\n"));
+        }
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if !request.tools.is_empty() {
+            body["tools"] = request.tools.iter().map(|t| self.format_tool(t)).collect();
+        }
+        body
+    }
+
+    fn parse_response(&self, body: &Value) -> Result<AgentResponse, AgentError> {
+        Ok(decode::<AnthropicMessageResponse>(body)?.into())
+    }
+}
+
+/// Gemini `generateContent` format
+///
+/// The model is part of the URL rather than the body (see
+/// `GeminiProvider`), so `build_request` ignores it. System messages move
+/// to `systemInstruction`, the assistant is the `model` role, tools are
+/// `functionDeclarations`, and tool results are `functionResponse` parts
+/// of a `user` turn; consecutive results share one turn.
+/// `request.safety_settings` is sent as `safetySettings`.
+pub struct GeminiFamily;
+
+impl ModelFamily for GeminiFamily {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn role_name(&self, role: Role) -> &'static str {
+        match role {
+            Role::Assistant => "model",
+            Role::Tool => "user",
+            other => other.as_str(),
+        }
+    }
+
+    fn format_tool(&self, tool: &ToolSpec) -> Value {
+        json!({
+            "name": tool.name,
+            "description": tool.description,
+            "parameters": tool.parameters,
+        })
+    }
+
+    fn format_message(&self, message: &Message) -> Value {
+        let role = self.role_name(message.role);
+        if let Some(result) = &message.tool_result {
+            let response = match result.is_error {
+                true => json!({"error": result.content}),
+                false => json!({"content": result.content}),
+            };
+            return json!({"role": role, "parts": [{
+                "functionResponse": {"name": result.name, "response": response},
+            }]});
+        }
+        let mut parts = Vec::new();
+        if !message.content.is_empty() || message.tool_calls.is_empty() {
+            parts.push(json!({"text": message.content}));
+        }
+        for call in &message.tool_calls {
+            parts.push(json!({"functionCall": {"name": call.name, "args": call.arguments}}));
+        }
+        json!({"role": role, "parts": parts})
+    }
+
+    fn build_request(&self, _model: &str, messages: &[Message], request: &AgentRequest) -> Value {
+        let system: Vec<Value> = messages
+            .iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| json!({"text": m.content}))
+            .collect();
+        let mut contents: Vec<Value> = Vec::new();
+        for message in messages.iter().filter(|m| m.role != Role::System) {
+            let wire = self.format_message(message);
+            // Results of one step's tool calls go back in a single user turn
+            if message.tool_result.is_some() {
+                if let Some(Value::Array(parts)) = contents
+                    .last_mut()
+                    .filter(|last| last["parts"][0].get("functionResponse").is_some())
+                    .map(|last| &mut last["parts"])
+                {
+                    parts.push(wire["parts"][0].clone());
+                    continue;
+                }
+            }
+            contents.push(wire);
+        }
+
+        let mut body = json!({"contents": contents});
+        if !system.is_empty() {
+            body["systemInstruction"] = json!({"parts": system});
+        }
+        let mut config = serde_json::Map::new();
+        if let Some(temperature) = request.temperature {
+            config.insert("temperature".to_string(), json!(temperature));
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            config.insert("maxOutputTokens".to_string(), json!(max_tokens));
+        }
+        if !config.is_empty() {
+            body["generationConfig"] = Value::Object(config);
+        }
+        if !request.tools.is_empty() {
+            let declarations: Vec<Value> =
+                request.tools.iter().map(|t| self.format_tool(t)).collect();
+            body["tools"] = json!([{"functionDeclarations": declarations}]);
+        }
+        if let Some(safety_settings) = &request.safety_settings {
+            body["safetySettings"] = safety_settings.clone();
+        }
+        body
+    }
+
+    fn parse_response(&self, body: &Value) -> Result<AgentResponse, AgentError> {
+        decode::<GeminiGenerateResponse>(body)?.try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> AgentRequest {
+        AgentRequest {
+            task: "Hello".to_string(),
+            temperature: Some(0.5),
+            tools: vec![ToolSpec {
+                name: "search".to_string(),
+                description: "Search the web".to_string(),
+                parameters: json!({"type": "object"}),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn messages() -> Vec<Message> {
+        vec![Message::system("Be brief"), Message::user("Hello")]
+    }
+
+    #[test]
+    fn test_openai_family_request() {
+        let body = OpenAIFamily.build_request("gpt-4", &messages(), &request());
+        assert_eq!(
+            body["messages"][0],
+            json!({"role": "system", "content": "Be brief"})
+        );
+        assert_eq!(body["messages"][1]["role"], "user");
+        assert_eq!(body["tools"][0]["type"], "function");
+        assert_eq!(body["tools"][0]["function"]["name"], "search");
+        assert_eq!(body["temperature"], 0.5);
+        assert!(body.get("max_tokens").is_none());
+
+        let capped = AgentRequest {
+            max_tokens: Some(100),
+            ..request()
+        };
+        let body = OpenAIFamily.build_request("gpt-4", &messages(), &capped);
+        assert_eq!(body["max_tokens"], 100);
+    }
+
+    #[test]
+    fn test_anthropic_family_request() {
+        let body = AnthropicFamily.build_request("claude-3-sonnet", &messages(), &request());
+        assert_eq!(body["system"], "Be brief");
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["tools"][0]["input_schema"], json!({"type": "object"}));
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(AnthropicFamily.role_name(Role::Tool), "user");
+    }
+
+    #[test]
+    fn test_role_wire_names() {
+        let message: Message =
+            serde_json::from_str(r#"{"role": "assistant", "content": "Hi"}"#).unwrap();
+        assert_eq!(message, Message::assistant("Hi"));
+        assert_eq!(
+            serde_json::to_value(Message::tool("42")).unwrap(),
+            json!({"role": "tool", "content": "42"})
+        );
+
+        let error = serde_json::from_str::<Message>(r#"{"role": "sytem", "content": "x"}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("unknown variant `sytem`"), "{}", error);
+    }
+
+    #[test]
+    fn test_openai_family_response() {
+        let response = OpenAIFamily
+            .parse_response(&json!({
+                "choices": [{"message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "search", "arguments": "{\"query\":\"rust\"}"},
+                    }],
+                }}],
+            }))
+            .unwrap();
+        assert_eq!(response.result, "");
+        assert_eq!(response.tool_calls[0].name, "search");
+        assert_eq!(response.tool_calls[0].arguments, json!({"query": "rust"}));
+
+        assert!(OpenAIFamily
+            .parse_response(&json!({"choices": []}))
+            .is_err());
+    }
+
+    #[test]
+    fn test_anthropic_family_response() {
+        let response = AnthropicFamily
+            .parse_response(&json!({
+                "content": [
+                    {"type": "text", "text": "Let me search."},
+                    {"type": "tool_use", "id": "toolu_1", "name": "search", "input": {"query": "rust"}},
+                ],
+            }))
+            .unwrap();
+        assert_eq!(response.result, "Let me search.");
+        assert_eq!(response.thoughts[0].content, "Let me search.");
+        assert_eq!(response.tool_calls[0].id, "toolu_1");
+        assert_eq!(response.tool_calls[0].arguments, json!({"query": "rust"}));
+    }
+
+    /// Conversation answering every tool call of `response`, the second
+    /// result being a failure
+    fn tool_turns(response: &AgentResponse) -> Vec<Message> {
+        let mut messages = vec![
+            Message::user("Search rust"),
+            Message::tool_calls(response.result.as_str(), response.tool_calls.clone()),
+        ];
+        for (i, call) in response.tool_calls.iter().enumerate() {
+            messages.push(Message::tool_result(ToolResult {
+                call_id: call.id.clone(),
+                name: call.name.clone(),
+                content: format!("result {}", i + 1),
+                is_error: i == 1,
+            }));
+        }
+        messages
+    }
+
+    #[test]
+    fn test_openai_tool_result_round_trip() {
+        let response = OpenAIFamily
+            .parse_response(&json!({
+                "choices": [{"message": {"content": null, "tool_calls": [
+                    {"id": "call_1", "function": {"name": "search", "arguments": "{\"query\":\"rust\"}"}},
+                    {"id": "call_2", "function": {"name": "fetch", "arguments": "{\"url\":\"x\"}"}},
+                ]}}],
+            }))
+            .unwrap();
+        let body = OpenAIFamily.build_request("gpt-4", &tool_turns(&response), &request());
+        let wire = &body["messages"];
+        assert_eq!(wire[1]["role"], "assistant");
+        assert_eq!(wire[1]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(
+            wire[1]["tool_calls"][0]["function"]["arguments"],
+            "{\"query\":\"rust\"}"
+        );
+        assert_eq!(
+            wire[2],
+            json!({"role": "tool", "tool_call_id": "call_1", "content": "result 1"})
+        );
+        assert_eq!(wire[3]["tool_call_id"], "call_2");
+
+        // The assistant turn parses back into the same tool calls
+        let echoed = OpenAIFamily
+            .parse_response(&json!({"choices": [{"message": wire[1]}]}))
+            .unwrap();
+        assert_eq!(echoed.tool_calls, response.tool_calls);
+    }
+
+    #[test]
+    fn test_anthropic_tool_result_round_trip() {
+        let response = AnthropicFamily
+            .parse_response(&json!({
+                "content": [
+                    {"type": "text", "text": "Let me search."},
+                    {"type": "tool_use", "id": "toolu_1", "name": "search", "input": {"query": "rust"}},
+                    {"type": "tool_use", "id": "toolu_2", "name": "fetch", "input": {"url": "x"}},
+                ],
+            }))
+            .unwrap();
+        let body =
+            AnthropicFamily.build_request("claude-3-sonnet", &tool_turns(&response), &request());
+        let wire = &body["messages"];
+        // Both results share one user turn
+        assert_eq!(wire.as_array().unwrap().len(), 3);
+        assert_eq!(
+            wire[1]["content"][0],
+            json!({"type": "text", "text": "Let me search."})
+        );
+        assert_eq!(wire[2]["role"], "user");
+        assert_eq!(
+            wire[2]["content"],
+            json!([
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "result 1", "is_error": false},
+                {"type": "tool_result", "tool_use_id": "toolu_2", "content": "result 2", "is_error": true},
+            ])
+        );
+
+        let echoed = AnthropicFamily
+            .parse_response(&json!({"content": wire[1]["content"]}))
+            .unwrap();
+        assert_eq!(echoed.tool_calls, response.tool_calls);
+        assert_eq!(echoed.result, "Let me search.");
+    }
+
+    #[test]
+    fn test_gemini_family_request_and_response() {
+        let response = GeminiFamily
+            .parse_response(&json!({
+                "candidates": [{"content": {"role": "model", "parts": [
+                    {"text": "Let me search."},
+                    {"functionCall": {"name": "search", "args": {"query": "rust"}}},
+                    {"functionCall": {"name": "fetch", "args": {"url": "x"}}},
+                ]}}],
+                "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 5},
+            }))
+            .unwrap();
+        assert_eq!(response.result, "Let me search.");
+        assert_eq!(response.tool_calls[1].id, "call_2");
+        assert_eq!(response.usage.unwrap().total(), 17);
+
+        let mut messages = vec![Message::system("Be brief")];
+        messages.extend(tool_turns(&response));
+        let body = GeminiFamily.build_request("gemini-1.5-pro", &messages, &request());
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "Be brief");
+        assert_eq!(body["generationConfig"]["temperature"], 0.5);
+        assert_eq!(
+            body["tools"][0]["functionDeclarations"][0]["name"],
+            "search"
+        );
+        let wire = body["contents"].as_array().unwrap();
+        // Both results share one user turn
+        assert_eq!(wire.len(), 3);
+        assert_eq!(wire[1]["role"], "model");
+        assert_eq!(
+            wire[1]["parts"][1],
+            json!({"functionCall": {"name": "search", "args": {"query": "rust"}}})
+        );
+        assert_eq!(
+            wire[2]["parts"][1],
+            json!({"functionResponse": {"name": "fetch", "response": {"error": "result 2"}}})
+        );
+
+        let blocked = GeminiFamily.parse_response(&json!({
+            "candidates": [{"finishReason": "SAFETY"}],
+        }));
+        assert!(matches!(blocked, Err(AgentError::ApiError(e)) if e.contains("SAFETY")));
+    }
+
+    #[test]
+    fn test_safety_settings_only_sent_by_gemini() {
+        let request: AgentRequest = serde_json::from_value(json!({
+            "task": "Hello",
+            "safety_settings": [{"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE"}],
+        }))
+        .unwrap();
+        assert!(request.safety_settings.is_some());
+
+        let families: [&dyn ModelFamily; 2] = [&OpenAIFamily, &AnthropicFamily];
+        for family in families {
+            let body = family.build_request("model", &messages(), &request);
+            assert!(!body.to_string().contains("HARM_CATEGORY"));
+        }
+        let body = GeminiFamily.build_request("model", &messages(), &request);
+        assert_eq!(body["safetySettings"], request.safety_settings.unwrap());
+    }
+}
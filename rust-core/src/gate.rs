@@ -0,0 +1,90 @@
+//! Relevance gates - decide whether a task needs retrieval at all
+
+use crate::{AgentRequest, LLMProvider};
+use async_trait::async_trait;
+
+/// Gate trait
+///
+/// Asked once per run before the vector store is searched; returning
+/// false skips retrieval and sets `AgentResponse.retrieval_skipped`.
+#[async_trait]
+pub trait RetrievalGate: Send + Sync {
+    async fn should_retrieve(&self, task: &str) -> bool;
+}
+
+/// Retrieves only for tasks of at least `min_words` words
+///
+/// A cheap heuristic that skips greetings and one-line small talk.
+#[derive(Debug, Clone)]
+pub struct MinWordsGate {
+    min_words: usize,
+}
+
+impl MinWordsGate {
+    pub fn new(min_words: usize) -> Self {
+        Self { min_words }
+    }
+}
+
+#[async_trait]
+impl RetrievalGate for MinWordsGate {
+    async fn should_retrieve(&self, task: &str) -> bool {
+        task.split_whitespace().count() >= self.min_words
+    }
+}
+
+/// Instruction sent to the gate model ahead of the task
+const MODEL_GATE_PROMPT: &str = "Would answering the following task benefit from looking up \
+documents? Reply with only yes or no.\n\nTask: ";
+
+/// Asks a (preferably small and fast) model whether to retrieve
+///
+/// Anything but an answer starting with "no" retrieves, including provider
+/// failures, so a broken gate costs latency rather than answer quality.
+pub struct ModelGate {
+    provider: Box<dyn LLMProvider>,
+}
+
+impl ModelGate {
+    pub fn new(provider: Box<dyn LLMProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+#[async_trait]
+impl RetrievalGate for ModelGate {
+    async fn should_retrieve(&self, task: &str) -> bool {
+        let request = AgentRequest {
+            task: format!("{}{}", MODEL_GATE_PROMPT, task),
+            max_tokens: Some(1),
+            ..Default::default()
+        };
+        match self.provider.chat(request).await {
+            Ok(response) => !response.result.trim().to_lowercase().starts_with("no"),
+            Err(e) => {
+                tracing::warn!(error = %e, "retrieval gate failed, retrieving anyway");
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockProvider;
+
+    #[tokio::test]
+    async fn test_built_in_gates() {
+        let gate = MinWordsGate::new(3);
+        assert!(!gate.should_retrieve("hi there").await);
+        assert!(gate.should_retrieve("what is our refund policy").await);
+
+        // The mock echoes the prompt, which is not a "no"
+        assert!(
+            ModelGate::new(Box::new(MockProvider::new()))
+                .should_retrieve("hello")
+                .await
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Hybrid search - fuse BM25 keyword scores with vector similarity
+
+use std::collections::{HashMap, HashSet};
+
+/// BM25 term-frequency saturation
+const BM25_K1: f32 = 1.2;
+/// BM25 document-length normalization
+const BM25_B: f32 = 0.75;
+
+/// How keyword and vector rankings are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FusionMethod {
+    /// Weighted sum of the cosine score and the BM25 score scaled to `0..=1`
+    Linear,
+    /// Weighted reciprocal rank fusion, `w / (k + rank)` summed over both rankings
+    ///
+    /// Ignores score magnitudes, so it is robust when the two scores are not
+    /// comparable, but the fused scores are no longer similarities.
+    ReciprocalRank { k: f32 },
+}
+
+/// Hybrid search settings for `MemoryVectorStore`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HybridSearch {
+    /// Share of the fused score taken from vector similarity, `0.0..=1.0`
+    ///
+    /// `1.0` is pure vector search and `0.0` pure keyword search.
+    pub vector_weight: f32,
+    pub fusion: FusionMethod,
+}
+
+impl HybridSearch {
+    pub fn new(vector_weight: f32) -> Self {
+        Self {
+            vector_weight: vector_weight.clamp(0.0, 1.0),
+            fusion: FusionMethod::Linear,
+        }
+    }
+
+    pub fn with_fusion(mut self, fusion: FusionMethod) -> Self {
+        self.fusion = fusion;
+        self
+    }
+
+    /// Fused score of every document from its cosine and BM25 scores
+    pub(crate) fn fuse(&self, vector: &[f32], keyword: &[f32]) -> Vec<f32> {
+        let w = self.vector_weight;
+        match self.fusion {
+            FusionMethod::Linear => {
+                let max = keyword.iter().copied().fold(0.0, f32::max);
+                vector
+                    .iter()
+                    .zip(keyword)
+                    .map(|(v, k)| {
+                        let k = if max > 0.0 { k / max } else { 0.0 };
+                        w * v + (1.0 - w) * k
+                    })
+                    .collect()
+            }
+            FusionMethod::ReciprocalRank { k } => {
+                let vector_ranks = ranks(vector);
+                let keyword_ranks = ranks(keyword);
+                vector_ranks
+                    .iter()
+                    .zip(&keyword_ranks)
+                    .map(|(rv, rk)| w / (k + rv) + (1.0 - w) / (k + rk))
+                    .collect()
+            }
+        }
+    }
+}
+
+impl Default for HybridSearch {
+    /// Pure vector search
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+/// 1-based rank of every score, highest first
+fn ranks(scores: &[f32]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+    let mut ranks = vec![0.0; scores.len()];
+    for (rank, i) in order.into_iter().enumerate() {
+        ranks[i] = (rank + 1) as f32;
+    }
+    ranks
+}
+
+/// BM25 score of every document for `query`
+///
+/// Documents and query are token lists as produced by `tokenize`.
+pub(crate) fn bm25_scores(query: &[String], documents: &[&[String]]) -> Vec<f32> {
+    if documents.is_empty() {
+        return Vec::new();
+    }
+
+    let n = documents.len() as f32;
+    let average_len = documents.iter().map(|d| d.len()).sum::<usize>() as f32 / n;
+    let terms: HashSet<&String> = query.iter().collect();
+    let idf: HashMap<&String, f32> = terms
+        .iter()
+        .map(|term| {
+            let df = documents.iter().filter(|d| d.contains(term)).count() as f32;
+            (*term, ((n - df + 0.5) / (df + 0.5) + 1.0).ln())
+        })
+        .collect();
+
+    documents
+        .iter()
+        .map(|document| {
+            let len = document.len() as f32;
+            terms
+                .iter()
+                .map(|term| {
+                    let tf = document.iter().filter(|t| t == term).count() as f32;
+                    let norm = BM25_K1 * (1.0 - BM25_B + BM25_B * len / average_len.max(1.0));
+                    idf[term] * tf * (BM25_K1 + 1.0) / (tf + norm)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<String> {
+        crate::embedding::tokenize(text).collect()
+    }
+
+    #[test]
+    fn test_bm25_prefers_rare_terms() {
+        let documents = [
+            tokens("the parser reported an error"),
+            tokens("the parser is fast"),
+            tokens("error code E1234"),
+        ];
+        let documents: Vec<&[String]> = documents.iter().map(|d| d.as_slice()).collect();
+        let scores = bm25_scores(&tokens("parser E1234"), &documents);
+        assert!(scores[2] > scores[0]);
+        assert!(scores[0] > 0.0);
+        assert_eq!(bm25_scores(&tokens("missing"), &documents), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_fusion_weights() {
+        let vector = [0.9, 0.1];
+        let keyword = [0.0, 4.0];
+
+        assert_eq!(HybridSearch::new(1.0).fuse(&vector, &keyword), vector);
+        assert_eq!(HybridSearch::new(0.0).fuse(&vector, &keyword), [0.0, 1.0]);
+
+        let rrf = HybridSearch::new(0.5)
+            .with_fusion(FusionMethod::ReciprocalRank { k: 60.0 })
+            .fuse(&vector, &keyword);
+        assert_eq!(rrf[0], rrf[1]);
+    }
+}
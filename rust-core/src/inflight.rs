@@ -0,0 +1,90 @@
+//! In-flight runs - what the agent is working on right now
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Snapshot of one run that has started and not yet finished
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InflightRun {
+    /// Id shared with the run's `AgentEvent`s
+    pub run_id: u64,
+    /// Model step in progress; 0 while retrieving
+    pub step: usize,
+    pub elapsed_ms: u64,
+    /// Provider and model the run asked for, if it chose them
+    pub provider: Option<String>,
+    pub model: Option<String>,
+}
+
+struct Entry {
+    started: Instant,
+    step: usize,
+    provider: Option<String>,
+    model: Option<String>,
+}
+
+/// Runs in progress, keyed by run id
+#[derive(Default)]
+pub(crate) struct InflightRegistry {
+    runs: Mutex<BTreeMap<u64, Entry>>,
+}
+
+impl InflightRegistry {
+    /// Track a run until the returned guard is dropped
+    pub(crate) fn start(
+        self: &Arc<Self>,
+        run_id: u64,
+        started: Instant,
+        provider: Option<String>,
+        model: Option<String>,
+    ) -> InflightGuard {
+        let entry = Entry {
+            started,
+            step: 0,
+            provider,
+            model,
+        };
+        self.runs.lock().unwrap().insert(run_id, entry);
+        InflightGuard {
+            registry: self.clone(),
+            run_id,
+        }
+    }
+
+    pub(crate) fn set_step(&self, run_id: u64, step: usize) {
+        if let Some(entry) = self.runs.lock().unwrap().get_mut(&run_id) {
+            entry.step = step;
+        }
+    }
+
+    /// Every tracked run, oldest first, with elapsed time up to `now`
+    pub(crate) fn snapshot(&self, now: Instant) -> Vec<InflightRun> {
+        self.runs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&run_id, entry)| InflightRun {
+                run_id,
+                step: entry.step,
+                elapsed_ms: now.saturating_duration_since(entry.started).as_millis() as u64,
+                provider: entry.provider.clone(),
+                model: entry.model.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Removes its run from the registry when dropped, including when the run
+/// fails or its future is dropped mid-way
+pub(crate) struct InflightGuard {
+    registry: Arc<InflightRegistry>,
+    run_id: u64,
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.registry.runs.lock().unwrap().remove(&self.run_id);
+    }
+}
@@ -3,6 +3,94 @@
 use serde::{Deserialize, Serialize};
 use async_trait::async_trait;
 use thiserror::Error;
+use std::sync::{Arc, RwLock};
+use std::task::Poll;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::Instrument;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+mod approval;
+mod audit;
+mod cancel;
+mod chaos;
+mod chunking;
+mod clock;
+mod committee;
+mod composite;
+mod context;
+mod embedding;
+mod eval;
+mod events;
+mod family;
+mod gate;
+mod hybrid;
+mod inflight;
+mod lint;
+mod loader;
+mod metrics;
+mod mock;
+mod patch;
+mod protocol;
+mod reconnect;
+mod recording;
+mod redact;
+mod responses;
+mod router;
+mod run_state;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod sse;
+mod stream;
+mod swap;
+mod tools;
+mod trace;
+mod transport;
+
+pub use approval::{ApprovalQueue, ToolApprover, TOOL_DENIED};
+pub use audit::{AuditRecord, AuditSink, AuditTransport, JsonlAuditSink};
+pub use cancel::CancellationToken;
+pub use chaos::{ChaosFault, ChaosProvider};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use committee::{CommitteeProvider, DEFAULT_JUDGE_PROMPT};
+pub use composite::CompositeVectorStore;
+pub use embedding::{Embedder, HashEmbedder, TruncatingEmbedder, TruncationStrategy};
+pub use eval::{evaluate_retrieval, LabeledQuery, QueryReport, RetrievalReport};
+pub use events::{AgentEvent, AgentEventKind, EventSink, JsonlSink};
+pub use family::{AnthropicFamily, GeminiFamily, Message, ModelFamily, OpenAIFamily, Role};
+pub use gate::{MinWordsGate, ModelGate, RetrievalGate};
+pub use hybrid::{FusionMethod, HybridSearch};
+pub use inflight::InflightRun;
+pub use lint::{EmptySectionLinter, LintIssue, MaxLengthLinter, PromptLinter};
+pub use loader::{ingest_documents, DocumentLoader, FileLoader, LoadedDocument};
+pub use metrics::{Histogram, Metrics, ToolStats, ToolUsage};
+pub use mock::MockProvider;
+pub use patch::{apply_merge_patch, merge_patch, parse_partial_json};
+pub use protocol::{ClientMessage, ServerMessage};
+pub use reconnect::ReconnectingProvider;
+pub use recording::{RecordedConversation, RecordedTurn};
+pub use redact::{redact_json, HeaderRedactor, REDACTED, TOOL_ARGUMENT_PATHS};
+pub use responses::{
+    AnthropicContentBlock, AnthropicMessageResponse, GeminiCandidate, GeminiContent,
+    GeminiFunctionCall, GeminiGenerateResponse, GeminiPart, OpenAIChatResponse, OpenAIChoice,
+    OpenAIFunctionCall, OpenAIMessage, OpenAIToolCall, Usage,
+};
+pub use router::ProviderRouter;
+pub use run_state::{RunOutcome, RunState};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteVectorStore;
+pub use sse::ChatStreamDecoder;
+pub use stream::StreamEvent;
+pub use swap::SwappableVectorStore;
+pub use tools::{Tool, ToolCall, ToolProgressSender, ToolResult, ToolSpec};
+pub use trace::TraceNode;
+pub use transport::{
+    HttpRequest, HttpResponse, HttpTransport, MockTransport, ReqwestTransport, TransportConfig,
+};
+
+use embedding::{cosine_similarity, dot, fnv1a, l2_normalize, tokenize};
+use inflight::InflightRegistry;
+use patch::PatchStream;
 
 /// Agent error types
 #[derive(Error, Debug)]
@@ -13,48 +101,377 @@ pub enum AgentError {
     NetworkError(#[from] reqwest::Error),
     #[error("Parse error: {0}")]
     ParseError(String),
+    /// The request failed validation before anything was sent
+    #[error("Validation error: {0}")]
+    Validation(String),
+    /// The provider rejected the API key (HTTP 401 or 403)
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    /// A stream stalled; `partial` is the content received before it did
+    #[error("Timeout: {message}")]
+    Timeout { message: String, partial: String },
+    /// A job was stopped through its `CancellationToken`
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+    /// A run used more than its token budget; `thoughts` is its trace up
+    /// to the step that passed the budget
+    ///
+    /// Its own variant rather than an `ApiError`, so the trace can travel
+    /// with the error.
+    #[error("Token budget exceeded: {used} of {budget} tokens used")]
+    TokenBudgetExceeded {
+        used: u64,
+        budget: u64,
+        steps: usize,
+        thoughts: Vec<Thought>,
+    },
 }
 
 /// Thought represents a reasoning step
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Thought {
     pub thought_type: String,
     pub content: String,
 }
 
 /// Agent request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AgentRequest {
     pub task: String,
     pub model: Option<String>,
     pub temperature: Option<f32>,
+    /// Earlier conversation turns, sent before the task
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Message>,
+    /// Tool calls and results of the current run, sent after the task
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub turns: Vec<Message>,
+    /// Tools the model may call
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolSpec>,
+    /// Provider-specific content safety configuration, passed through as-is
+    ///
+    /// `GeminiProvider` sends it as `safetySettings` (safety category
+    /// thresholds); the OpenAI and Anthropic families ignore it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_settings: Option<serde_json::Value>,
+    /// Cap on generated tokens; families that require one default to 4096
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Start of the answer, sent as a trailing assistant message for the
+    /// model to continue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefill: Option<String>,
+    /// Language to answer in, as a name or tag (`French`, `pt-BR`)
+    ///
+    /// Sent as a system instruction ahead of the task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Named provider a `ProviderRouter` sends this request to; other
+    /// providers ignore it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Log this request verbosely (see `RunOptions::debug`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub debug: bool,
+    /// HTTP headers added to this call only, replacing the provider's own
+    /// headers of the same name (e.g. a tenant's `authorization`)
+    #[serde(skip)]
+    pub headers: HeaderMap,
+}
+
+impl AgentRequest {
+    /// Conversation sent to the model: the history, any locale instruction,
+    /// the task, then any prefill
+    pub fn messages(&self) -> Vec<Message> {
+        let mut messages = self.history.clone();
+        if let Some(locale) = &self.locale {
+            messages.push(Message::system(format!("Respond in {}.", locale)));
+        }
+        messages.push(Message::user(self.task.as_str()));
+        messages.extend(self.turns.iter().cloned());
+        if let Some(prefill) = &self.prefill {
+            messages.push(Message::assistant(prefill.as_str()));
+        }
+        messages
+    }
+
+    /// Stable identity of the request, for caching, coalescing and
+    /// idempotency keys
+    ///
+    /// A 64-bit FNV-1a hash of the request's JSON with object keys sorted
+    /// at every level, so it does not depend on field or map order and is
+    /// the same across processes. `headers` and the fields in
+    /// `NON_SEMANTIC_FIELDS`, which do not change the answer, are not part
+    /// of the identity. Features keying on requests should use this rather
+    /// than hashing requests themselves.
+    pub fn canonical_hash(&self) -> u64 {
+        let mut canonical = String::new();
+        // Only non-string map keys could fail to serialize, and there are none
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let serde_json::Value::Object(fields) = &mut value {
+            for field in NON_SEMANTIC_FIELDS {
+                fields.remove(field);
+            }
+        }
+        write_canonical(&value, &mut canonical);
+        fnv1a(canonical.as_bytes())
+    }
+}
+
+/// `AgentRequest` fields left out of `canonical_hash`: how a request is
+/// logged and routed, not what it asks
+const NON_SEMANTIC_FIELDS: [&str; 2] = ["debug", "provider"];
+
+/// Append `value` to `out` as compact JSON with sorted object keys
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
 }
 
 /// Agent response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AgentResponse {
     pub result: String,
     pub thoughts: Vec<Thought>,
     pub duration_ms: u64,
+    /// Tools the model asked to call before it can answer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Older thoughts were dropped to respect the agent's `max_thoughts`
+    #[serde(default)]
+    pub thoughts_truncated: bool,
+    /// Milliseconds from request start to the first content delta
+    ///
+    /// Only streaming runs measure this; it is `None` for non-streaming calls.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttft_ms: Option<u64>,
+    /// Non-fatal issues hit during the run, e.g. a failed tool the agent worked around
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// The run hit its soft deadline and `result` is the last model output
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Vector store hits retrieved for the task
+    #[serde(default)]
+    pub retrieved_chunks: usize,
+    /// Retrieved chunks that fit the context budget and were sent
+    #[serde(default)]
+    pub used_chunks: usize,
+    /// Retrieved chunks left out because they did not fit the budget
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped_chunks: Vec<String>,
+    /// `result` was cut to the agent's `max_result_chars`
+    #[serde(default)]
+    pub truncated: bool,
+    /// The agent's relevance gate decided the task needs no retrieval
+    #[serde(default)]
+    pub retrieval_skipped: bool,
+    /// Name of the provider that produced the response, e.g. `openai`
+    ///
+    /// Wrappers pass their inner provider's name through, so behind a
+    /// fallback or router this names the provider that actually answered.
+    /// Empty when a provider does not set it.
+    #[serde(default)]
+    pub provider: String,
+    /// Steps as a tree, with sub-agent runs nested under their tool calls
+    ///
+    /// Only set by agents built with `ReActAgent::with_trace_tree`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Vec<TraceNode>>,
+    /// The run was a dry run: `tool_calls` lists the tools the agent would
+    /// call next and none were executed
+    #[serde(default)]
+    pub plan_only: bool,
+    /// Tokens consumed, summed over every model call of the run
+    ///
+    /// `None` when the provider reported no usage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 /// LLM Provider trait
 #[async_trait]
 pub trait LLMProvider: Send + Sync {
     async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError>;
+
+    /// Like `chat`, sending generated content on `deltas` as it arrives
+    ///
+    /// Providers without native streaming send the whole result as a single
+    /// delta once the response is complete.
+    async fn chat_stream(
+        &self,
+        request: AgentRequest,
+        deltas: UnboundedSender<String>,
+    ) -> Result<AgentResponse, AgentError> {
+        let response = self.chat(request).await?;
+        if !response.result.is_empty() {
+            let _ = deltas.send(response.result.clone());
+        }
+        Ok(response)
+    }
+
+    /// Check the provider's configuration without calling the model
+    ///
+    /// Meant to run once at startup, so misconfiguration is reported before
+    /// the first request; implementations must stay cheap.
+    async fn validate(&self) -> Result<(), AgentError> {
+        Ok(())
+    }
+
+    /// One-time setup before the provider serves requests
+    ///
+    /// For providers that need a handshake (an auth exchange, capability
+    /// negotiation) and cache its result for `chat`. Called once by
+    /// `ReActAgent::init`; the default does nothing.
+    async fn init(&self) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+/// Send the same request to every provider and return the first success
+///
+/// `race_stream` without the deltas: the winner is the first provider to
+/// produce a token, not the first to finish.
+pub async fn race(
+    providers: &[Box<dyn LLMProvider>],
+    request: AgentRequest,
+) -> Result<AgentResponse, AgentError> {
+    let (deltas, _) = tokio::sync::mpsc::unbounded_channel();
+    race_stream(providers, request, deltas).await
+}
+
+/// Stream the same request from every provider and keep the first to
+/// produce a token
+///
+/// Every provider is called with `chat_stream`. As soon as one sends its
+/// first delta (or finishes, for a response without content) the other
+/// calls are dropped, which aborts their in-flight HTTP requests, and the
+/// winner's deltas are forwarded to `deltas` until it completes. Providers
+/// that fail before a winner is found are skipped; if all of them fail the
+/// last error is returned. Once a provider has won, its failure is the
+/// race's failure.
+pub async fn race_stream(
+    providers: &[Box<dyn LLMProvider>],
+    request: AgentRequest,
+    deltas: UnboundedSender<String>,
+) -> Result<AgentResponse, AgentError> {
+    if providers.is_empty() {
+        return Err(AgentError::ApiError(
+            "race requires at least one provider".to_string(),
+        ));
+    }
+
+    let mut contenders: Vec<_> = providers
+        .iter()
+        .map(|provider| {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            (provider.chat_stream(request.clone(), tx), rx)
+        })
+        .collect();
+    let mut last_error = None;
+    let lead = futures::future::poll_fn(|cx| {
+        let mut i = 0;
+        while i < contenders.len() {
+            let (call, rx) = &mut contenders[i];
+            match call.as_mut().poll(cx) {
+                Poll::Ready(Ok(response)) => return Poll::Ready(Some((i, Ok(response)))),
+                Poll::Ready(Err(e)) => {
+                    last_error = Some(e);
+                    drop(contenders.swap_remove(i));
+                    continue;
+                }
+                Poll::Pending => {}
+            }
+            if let Poll::Ready(Some(delta)) = rx.poll_recv(cx) {
+                return Poll::Ready(Some((i, Err(delta))));
+            }
+            i += 1;
+        }
+        match contenders.is_empty() {
+            true => Poll::Ready(None),
+            false => Poll::Pending,
+        }
+    })
+    .await;
+    let Some((winner, lead)) = lead else {
+        return Err(last_error.unwrap_or_else(|| {
+            AgentError::ApiError("race requires at least one provider".to_string())
+        }));
+    };
+
+    // Dropping the losers cancels their calls
+    let (mut call, mut rx) = contenders.swap_remove(winner);
+    drop(contenders);
+    let response = match lead {
+        Ok(response) => response,
+        Err(first) => {
+            let _ = deltas.send(first);
+            loop {
+                tokio::select! {
+                    Some(delta) = rx.recv() => {
+                        let _ = deltas.send(delta);
+                    }
+                    response = &mut call => break response?,
+                }
+            }
+        }
+    };
+    while let Ok(delta) = rx.try_recv() {
+        let _ = deltas.send(delta);
+    }
+    Ok(response)
 }
 
 /// OpenAI Provider
 pub struct OpenAIProvider {
+    name: String,
     api_key: String,
     model: String,
+    base_url: String,
+    family: Box<dyn ModelFamily>,
+    transport: Arc<dyn HttpTransport>,
+    /// `chat_stream` asks for an event stream
+    streaming: bool,
 }
 
 impl OpenAIProvider {
     pub fn new(api_key: String) -> Self {
         Self {
+            name: "openai".to_string(),
             api_key,
             model: "gpt-4".to_string(),
+            base_url: "https://api.openai.com/v1".to_string(),
+            family: Box::new(OpenAIFamily),
+            transport: Arc::new(ReqwestTransport::new()),
+            streaming: false,
         }
     }
 
@@ -62,102 +479,835 @@ impl OpenAIProvider {
         self.model = model;
         self
     }
+
+    /// Override the request format, e.g. for a gateway fronting another API
+    pub fn with_family(mut self, family: Box<dyn ModelFamily>) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Report responses as coming from `name` instead of `openai`
+    ///
+    /// For OpenAI-compatible servers such as ollama or vLLM.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Send requests somewhere other than `https://api.openai.com/v1`
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Send requests through `transport` instead of the default reqwest client
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Request `stream: true` responses in `chat_stream` and decode them
+    /// with `ChatStreamDecoder`
+    ///
+    /// For servers speaking the OpenAI chat completions format; streams
+    /// may end with or without `[DONE]`. Transports return whole bodies,
+    /// so the deltas are forwarded once the response has arrived. Off by
+    /// default, sending the full answer as one delta.
+    pub fn with_streaming(mut self) -> Self {
+        self.streaming = true;
+        self
+    }
+
+    /// Chat request body for `request`, built by the provider's model family
+    pub fn build_request(&self, request: &AgentRequest) -> serde_json::Value {
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        self.family
+            .build_request(model, &request.messages(), request)
+    }
+
+    fn headers(&self, request: &AgentRequest) -> Result<HeaderMap, AgentError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            header_value(&format!("Bearer {}", self.api_key))?,
+        );
+        Ok(with_request_headers(headers, request))
+    }
 }
 
 #[async_trait]
 impl LLMProvider for OpenAIProvider {
     async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
         let start = std::time::Instant::now();
-        
-        // Simulate API call
-        let thoughts = vec![
-            Thought {
-                thought_type: "thought".to_string(),
-                content: format!("Analyzing: {}", request.task),
-            },
-            Thought {
-                thought_type: "action".to_string(),
-                content: "Generate response".to_string(),
-            },
-        ];
 
-        let result = format!("OpenAI response for: {}", request.task);
-        
-        Ok(AgentResponse {
-            result,
-            thoughts,
-            duration_ms: start.elapsed().as_millis() as u64,
-        })
+        let url = format!("{}/chat/completions", self.base_url);
+        let body = transport::post_json(
+            self.transport.as_ref(),
+            &url,
+            self.headers(&request)?,
+            &self.build_request(&request),
+        )
+        .await?;
+
+        let mut response = self.family.parse_response(&body)?;
+        response.duration_ms = start.elapsed().as_millis() as u64;
+        response.provider = self.name.clone();
+        Ok(response)
+    }
+
+    async fn chat_stream(
+        &self,
+        request: AgentRequest,
+        deltas: UnboundedSender<String>,
+    ) -> Result<AgentResponse, AgentError> {
+        if !self.streaming {
+            let response = self.chat(request).await?;
+            if !response.result.is_empty() {
+                let _ = deltas.send(response.result.clone());
+            }
+            return Ok(response);
+        }
+        let start = std::time::Instant::now();
+
+        let url = format!("{}/chat/completions", self.base_url);
+        let mut body = self.build_request(&request);
+        body["stream"] = serde_json::json!(true);
+        let stream = transport::post_text(
+            self.transport.as_ref(),
+            &url,
+            self.headers(&request)?,
+            &body,
+        )
+        .await?;
+
+        let mut decoder = ChatStreamDecoder::new();
+        for delta in decoder.push(&stream)? {
+            let _ = deltas.send(delta);
+        }
+        let mut response = decoder.finish()?;
+        response.duration_ms = start.elapsed().as_millis() as u64;
+        response.provider = self.name.clone();
+        Ok(response)
+    }
+
+    async fn validate(&self) -> Result<(), AgentError> {
+        validate_config(&self.api_key, &self.base_url, &self.model)
     }
 }
 
 /// Anthropic Provider
 pub struct AnthropicProvider {
+    name: String,
     api_key: String,
     model: String,
+    base_url: String,
+    family: Box<dyn ModelFamily>,
+    transport: Arc<dyn HttpTransport>,
 }
 
 impl AnthropicProvider {
     pub fn new(api_key: String) -> Self {
         Self {
+            name: "anthropic".to_string(),
             api_key,
             model: "claude-3-sonnet".to_string(),
+            base_url: "https://api.anthropic.com/v1".to_string(),
+            family: Box::new(AnthropicFamily),
+            transport: Arc::new(ReqwestTransport::new()),
         }
     }
+
+    pub fn with_family(mut self, family: Box<dyn ModelFamily>) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Report responses as coming from `name` instead of `anthropic`
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Send requests somewhere other than `https://api.anthropic.com/v1`
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Send requests through `transport` instead of the default reqwest client
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Messages request body for `request`, built by the provider's model family
+    pub fn build_request(&self, request: &AgentRequest) -> serde_json::Value {
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        self.family
+            .build_request(model, &request.messages(), request)
+    }
+
+    fn headers(&self, request: &AgentRequest) -> Result<HeaderMap, AgentError> {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", header_value(&self.api_key)?);
+        headers.insert(
+            "anthropic-version",
+            HeaderValue::from_static(ANTHROPIC_VERSION),
+        );
+        Ok(with_request_headers(headers, request))
+    }
 }
 
 #[async_trait]
 impl LLMProvider for AnthropicProvider {
     async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
         let start = std::time::Instant::now();
-        
-        let thoughts = vec![
-            Thought {
-                thought_type: "thought".to_string(),
-                content: format!("Reasoning about: {}", request.task),
-            },
-        ];
 
-        let result = format!("Anthropic response for: {}", request.task);
-        
-        Ok(AgentResponse {
-            result,
-            thoughts,
-            duration_ms: start.elapsed().as_millis() as u64,
-        })
+        let url = format!("{}/messages", self.base_url);
+        let body = transport::post_json(
+            self.transport.as_ref(),
+            &url,
+            self.headers(&request)?,
+            &self.build_request(&request),
+        )
+        .await?;
+
+        let mut response = self.family.parse_response(&body)?;
+        response.duration_ms = start.elapsed().as_millis() as u64;
+        response.provider = self.name.clone();
+        Ok(response)
+    }
+
+    async fn validate(&self) -> Result<(), AgentError> {
+        validate_config(&self.api_key, &self.base_url, &self.model)
+    }
+}
+
+/// Gemini Provider
+///
+/// Posts to `models/{model}:generateContent`; request bodies follow
+/// `GeminiFamily`, so `safety_settings` reach Gemini's safety config.
+pub struct GeminiProvider {
+    name: String,
+    api_key: String,
+    model: String,
+    base_url: String,
+    family: Box<dyn ModelFamily>,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl GeminiProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            name: "gemini".to_string(),
+            api_key,
+            model: "gemini-1.5-pro".to_string(),
+            base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            family: Box::new(GeminiFamily),
+            transport: Arc::new(ReqwestTransport::new()),
+        }
+    }
+
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Report responses as coming from `name` instead of `gemini`
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Send requests somewhere other than
+    /// `https://generativelanguage.googleapis.com/v1beta`
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Send requests through `transport` instead of the default reqwest client
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// `generateContent` request body for `request`, built by the provider's
+    /// model family
+    pub fn build_request(&self, request: &AgentRequest) -> serde_json::Value {
+        self.family
+            .build_request(self.model(request), &request.messages(), request)
     }
+
+    /// Model named in the request, or the provider's
+    fn model<'a>(&'a self, request: &'a AgentRequest) -> &'a str {
+        request.model.as_deref().unwrap_or(&self.model)
+    }
+
+    fn headers(&self, request: &AgentRequest) -> Result<HeaderMap, AgentError> {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-goog-api-key", header_value(&self.api_key)?);
+        Ok(with_request_headers(headers, request))
+    }
+}
+
+#[async_trait]
+impl LLMProvider for GeminiProvider {
+    async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+        let start = std::time::Instant::now();
+
+        let url = format!(
+            "{}/models/{}:generateContent",
+            self.base_url,
+            self.model(&request)
+        );
+        let body = transport::post_json(
+            self.transport.as_ref(),
+            &url,
+            self.headers(&request)?,
+            &self.build_request(&request),
+        )
+        .await?;
+
+        let mut response = self.family.parse_response(&body)?;
+        response.duration_ms = start.elapsed().as_millis() as u64;
+        response.provider = self.name.clone();
+        Ok(response)
+    }
+
+    async fn validate(&self) -> Result<(), AgentError> {
+        validate_config(&self.api_key, &self.base_url, &self.model)
+    }
+}
+
+/// Anthropic API version sent with every messages request
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// `headers` with the request's own headers layered on top
+fn with_request_headers(mut headers: HeaderMap, request: &AgentRequest) -> HeaderMap {
+    for (name, value) in &request.headers {
+        headers.insert(name.clone(), value.clone());
+    }
+    headers
+}
+
+/// Checks shared by the HTTP providers: key, base URL and model are usable
+fn validate_config(api_key: &str, base_url: &str, model: &str) -> Result<(), AgentError> {
+    if api_key.trim().is_empty() {
+        return Err(AgentError::Validation("API key is empty".to_string()));
+    }
+    header_value(api_key).map_err(|e| AgentError::Validation(e.to_string()))?;
+    match reqwest::Url::parse(base_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        Ok(url) => {
+            return Err(AgentError::Validation(format!(
+                "base URL {} must use http or https, not {}",
+                base_url,
+                url.scheme()
+            )))
+        }
+        Err(e) => {
+            return Err(AgentError::Validation(format!(
+                "base URL {} is invalid: {}",
+                base_url, e
+            )))
+        }
+    }
+    if model.trim().is_empty() {
+        return Err(AgentError::Validation("model name is empty".to_string()));
+    }
+    Ok(())
+}
+
+/// Header value from a configured string such as an API key
+fn header_value(value: &str) -> Result<HeaderValue, AgentError> {
+    HeaderValue::from_str(value)
+        .map_err(|_| AgentError::ApiError("API key is not a valid header value".to_string()))
 }
 
 /// Vector store trait
 pub trait VectorStore: Send + Sync {
     fn add(&self, text: String, metadata: serde_json::Value) -> Result<(), AgentError>;
     fn search(&self, query: String, limit: usize) -> Result<Vec<(String, f32)>, AgentError>;
+
+    /// Hits `offset..offset + limit` of the full ranking for `query`
+    ///
+    /// Pages are consistent and never overlap as long as the store orders
+    /// hits totally, which the built-in stores do (score, then insertion
+    /// order or text). The default searches `offset + limit` hits and skips
+    /// the first `offset`.
+    fn search_page(
+        &self,
+        query: String,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<(String, f32)>, AgentError> {
+        let hits = self.search(query, offset + limit)?;
+        Ok(hits.into_iter().skip(offset).collect())
+    }
+
+    /// Like `search`, but also returns each hit's embedding vector
+    ///
+    /// Kept separate so plain searches never copy vectors. Stores that do
+    /// not keep embeddings return an error.
+    fn search_with_embeddings(
+        &self,
+        query: String,
+        limit: usize,
+    ) -> Result<Vec<(String, f32, Vec<f32>)>, AgentError> {
+        let _ = (query, limit);
+        Err(AgentError::ApiError(
+            "this vector store does not return embeddings".to_string(),
+        ))
+    }
+
+    /// Add `documents` in order, e.g. one batch of an ingestion job,
+    /// returning how many were added
+    ///
+    /// Stops with `AgentError::Cancelled` once `cancel` is set. The default
+    /// adds one document at a time and checks between them.
+    fn add_batch(
+        &self,
+        documents: Vec<(String, serde_json::Value)>,
+        cancel: &CancellationToken,
+    ) -> Result<usize, AgentError> {
+        let count = documents.len();
+        for (added, (text, metadata)) in documents.into_iter().enumerate() {
+            cancel.check(|| format!("added {} of {} documents", added, count))?;
+            self.add(text, metadata)?;
+        }
+        Ok(count)
+    }
+
+    /// Id of the document a hit's `text` belongs to, for matching hits
+    /// against labeled ids
+    ///
+    /// The default is `content_id(None, text)`.
+    fn document_id(&self, text: &str) -> String {
+        content_id(None, text)
+    }
+}
+
+/// Content-derived document id, as used by `MemoryVectorStore::upsert`
+///
+/// The text is normalized by trimming it and collapsing every run of
+/// whitespace to one space; case and punctuation are kept. The id is the
+/// 64-bit FNV-1a hash of the namespace, a NUL byte and the normalized text,
+/// as 16 lowercase hex digits (without a namespace, the hash of the text
+/// alone). Stable across processes and releases, so texts that differ
+/// only in whitespace get the same id in the same namespace.
+pub fn content_id(namespace: Option<&str>, text: &str) -> String {
+    let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let key = match namespace {
+        Some(namespace) => format!("{}\0{}", namespace, normalized),
+        None => normalized,
+    };
+    format!("{:016x}", fnv1a(key.as_bytes()))
+}
+
+/// Document held by `MemoryVectorStore`
+struct StoredDocument {
+    /// Set for documents added with `upsert`
+    id: Option<String>,
+    text: String,
+    metadata: serde_json::Value,
+    embedding: Vec<f32>,
+    /// Keyword tokens for BM25 scoring
+    tokens: Vec<String>,
+}
+
+/// Embedder used for documents whose metadata `field` equals `value`
+struct DocumentEmbedder {
+    field: String,
+    value: String,
+    embedder: Box<dyn Embedder>,
+}
+
+/// Score decay by the age in metadata `field`
+struct RecencyBoost {
+    field: String,
+    half_life: Duration,
 }
 
 /// In-memory vector store
 pub struct MemoryVectorStore {
-    documents: Vec<(String, serde_json::Value)>,
+    documents: RwLock<Vec<StoredDocument>>,
+    /// Embeds queries, and documents no `document_embedders` entry matches
+    embedder: Box<dyn Embedder>,
+    document_embedders: Vec<DocumentEmbedder>,
+    hybrid: HybridSearch,
+    /// Store unit vectors and score with a plain dot product
+    normalize: bool,
+    /// Decimal places returned scores are rounded to
+    score_decimals: Option<u32>,
+    recency: Option<RecencyBoost>,
+    /// Object merged under every added document's metadata
+    default_metadata: serde_json::Map<String, serde_json::Value>,
+    /// Namespace of the ids `upsert` derives
+    id_namespace: Option<String>,
+    /// Wall time the recency boost measures ages from
+    clock: Arc<dyn Clock>,
 }
 
 impl MemoryVectorStore {
     pub fn new() -> Self {
+        Self::with_embedder(Box::new(HashEmbedder::default()))
+    }
+
+    /// Store embedding with `embedder`
+    ///
+    /// Normalized scoring (see `with_normalized_embeddings`) is on from the
+    /// start if the embedder reports unit-length vectors.
+    pub fn with_embedder(embedder: Box<dyn Embedder>) -> Self {
         Self {
-            documents: Vec::new(),
+            documents: RwLock::new(Vec::new()),
+            normalize: embedder.normalizes(),
+            embedder,
+            document_embedders: Vec::new(),
+            hybrid: HybridSearch::default(),
+            score_decimals: None,
+            recency: None,
+            default_metadata: serde_json::Map::new(),
+            id_namespace: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Embed documents whose metadata `field` is the string `value` with `embedder`
+    ///
+    /// Queries are still embedded with the store's main embedder and compared
+    /// against every document, so `embedder` must produce vectors of the same
+    /// dimensions in the same space (e.g. a code-tuned variant of the query
+    /// model). Mismatched dimensions are rejected here; vectors from
+    /// unrelated models would have matching lengths but meaningless scores.
+    /// The first matching rule wins.
+    pub fn with_document_embedder(
+        mut self,
+        field: impl Into<String>,
+        value: impl Into<String>,
+        embedder: Box<dyn Embedder>,
+    ) -> Result<Self, AgentError> {
+        if embedder.dimensions() != self.embedder.dimensions() {
+            return Err(AgentError::ApiError(format!(
+                "document embedder has {} dimensions but the query embedder has {}",
+                embedder.dimensions(),
+                self.embedder.dimensions()
+            )));
+        }
+        self.document_embedders.push(DocumentEmbedder {
+            field: field.into(),
+            value: value.into(),
+            embedder,
+        });
+        Ok(self)
+    }
+
+    /// Index of the `document_embedders` rule matching `metadata`
+    fn embedder_rule(&self, metadata: &serde_json::Value) -> Option<usize> {
+        self.document_embedders
+            .iter()
+            .position(|rule| metadata[&rule.field].as_str() == Some(rule.value.as_str()))
+    }
+
+    /// Embedder for a document with `metadata`
+    fn embedder_for(&self, metadata: &serde_json::Value) -> &dyn Embedder {
+        match self.embedder_rule(metadata) {
+            Some(rule) => self.document_embedders[rule].embedder.as_ref(),
+            None => self.embedder.as_ref(),
+        }
+    }
+
+    /// Blend BM25 keyword scores into the ranking
+    ///
+    /// The default is pure vector search.
+    pub fn with_hybrid_search(mut self, hybrid: HybridSearch) -> Self {
+        self.hybrid = hybrid;
+        self
+    }
+
+    /// L2-normalize embeddings once on `add` and score with a dot product
+    ///
+    /// Cosine similarity is the dot product of the unit vectors, so scores
+    /// are the same up to float rounding while each comparison skips two
+    /// norm computations; worthwhile for large stores. Every vector is
+    /// checked, so pre-normalized and raw embeddings (e.g. from different
+    /// document embedders) can be mixed: unit vectors are kept as they are
+    /// and the rest are scaled. Zero vectors stay zero and still score 0.
+    /// Stored (and returned) embeddings are the normalized ones. Set this
+    /// before adding documents.
+    pub fn with_normalized_embeddings(mut self) -> Self {
+        self.normalize = true;
+        self
+    }
+
+    /// Round returned scores to `decimals` decimal places
+    ///
+    /// For snapshot tests: float scores differ in the last bits across
+    /// platforms, rounded ones do not. Hits are still ranked by the raw
+    /// scores. Off by default, returning raw scores.
+    pub fn with_score_rounding(mut self, decimals: u32) -> Self {
+        self.score_decimals = Some(decimals);
+        self
+    }
+
+    /// Favour newer documents: halve a document's score for every
+    /// `half_life` of age
+    ///
+    /// Age is measured from the Unix timestamp in seconds in metadata
+    /// `field` (e.g. `{"updated_at": 1700000000}`) to the store's clock
+    /// (see `with_clock`), after any hybrid fusion, so a fresh document can
+    /// outrank a somewhat more similar stale one. Documents without a
+    /// numeric timestamp keep their score, as do those dated in the future.
+    /// A `half_life` under a second counts as one second. Off by default.
+    pub fn with_recency_boost(mut self, field: impl Into<String>, half_life: Duration) -> Self {
+        self.recency = Some(RecencyBoost {
+            field: field.into(),
+            half_life: half_life.max(Duration::from_secs(1)),
+        });
+        self
+    }
+
+    /// Measure recency boost ages with `clock`'s wall time instead of the
+    /// system's
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Stamp every added document with the members of `defaults`
+    ///
+    /// A shallow merge: a document's own top-level metadata members win
+    /// over defaults of the same name, and nested objects are not merged.
+    /// Metadata that is not an object (e.g. `null`) is replaced by the
+    /// defaults. Defaults apply before `with_document_embedder` rules, so
+    /// rules can match them. Non-object `defaults` are ignored.
+    pub fn with_default_metadata(mut self, defaults: serde_json::Value) -> Self {
+        if let serde_json::Value::Object(defaults) = defaults {
+            self.default_metadata = defaults;
+        }
+        self
+    }
+
+    /// Derive `upsert` ids within `namespace`, so equal texts from
+    /// different corpora stay separate
+    pub fn with_id_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.id_namespace = Some(namespace.into());
+        self
+    }
+
+    /// Add `text` under its `content_id`, replacing any document with that id
+    ///
+    /// Re-ingesting identical content is idempotent: the document keeps its
+    /// place in the insertion order and takes the new metadata. Returns the
+    /// id. Documents added with `add` have no id and are never replaced.
+    pub fn upsert(&self, text: String, metadata: serde_json::Value) -> Result<String, AgentError> {
+        let id = content_id(self.id_namespace.as_deref(), &text);
+        let document = self.document(Some(id.clone()), text, metadata)?;
+        let mut documents = self.documents.write().unwrap();
+        match documents
+            .iter_mut()
+            .find(|existing| existing.id.as_deref() == Some(id.as_str()))
+        {
+            Some(existing) => *existing = document,
+            None => documents.push(document),
+        }
+        Ok(id)
+    }
+
+    /// Embedded document, ready to store
+    fn document(
+        &self,
+        id: Option<String>,
+        text: String,
+        metadata: serde_json::Value,
+    ) -> Result<StoredDocument, AgentError> {
+        let metadata = self.stamp(metadata);
+        let embedding = self.embedder_for(&metadata).embed(&text)?;
+        self.stored(id, text, metadata, embedding)
+    }
+
+    /// Document with its embedding, checked and ready to store
+    fn stored(
+        &self,
+        id: Option<String>,
+        text: String,
+        metadata: serde_json::Value,
+        mut embedding: Vec<f32>,
+    ) -> Result<StoredDocument, AgentError> {
+        if embedding.len() != self.embedder.dimensions() {
+            return Err(AgentError::ApiError(format!(
+                "document embedding has {} dimensions, expected {}",
+                embedding.len(),
+                self.embedder.dimensions()
+            )));
+        }
+        if self.normalize {
+            l2_normalize(&mut embedding);
+        }
+        let tokens = tokenize(&text).collect();
+        Ok(StoredDocument {
+            id,
+            text,
+            metadata,
+            embedding,
+            tokens,
+        })
+    }
+
+    /// `metadata` with the default metadata merged under it
+    fn stamp(&self, metadata: serde_json::Value) -> serde_json::Value {
+        if self.default_metadata.is_empty() {
+            return metadata;
+        }
+        let mut stamped = self.default_metadata.clone();
+        if let serde_json::Value::Object(own) = metadata {
+            stamped.extend(own);
+        }
+        serde_json::Value::Object(stamped)
+    }
+
+    /// Indices and scores of the best `limit` documents
+    ///
+    /// Scores are cosine similarities, fused with BM25 unless the store
+    /// does pure vector search. Equal scores rank in insertion order, so
+    /// the order is total and pages from `search_page` are stable.
+    fn rank(
+        &self,
+        documents: &[StoredDocument],
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<(usize, f32)>, AgentError> {
+        let mut embedding = self.embedder.embed(query)?;
+        let mut scores: Vec<f32> = if self.normalize {
+            l2_normalize(&mut embedding);
+            documents
+                .iter()
+                .map(|doc| dot(&embedding, &doc.embedding))
+                .collect()
+        } else {
+            documents
+                .iter()
+                .map(|doc| cosine_similarity(&embedding, &doc.embedding))
+                .collect()
+        };
+        if self.hybrid != HybridSearch::default() {
+            let query: Vec<String> = tokenize(query).collect();
+            let tokens: Vec<&[String]> =
+                documents.iter().map(|doc| doc.tokens.as_slice()).collect();
+            scores = self
+                .hybrid
+                .fuse(&scores, &hybrid::bm25_scores(&query, &tokens));
+        }
+        if let Some(recency) = &self.recency {
+            let now = self
+                .clock
+                .system_time()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            for (score, doc) in scores.iter_mut().zip(documents) {
+                if let Some(timestamp) = doc.metadata[&recency.field].as_f64() {
+                    let age = (now - timestamp).max(0.0);
+                    *score *= 0.5f64.powf(age / recency.half_life.as_secs_f64()) as f32;
+                }
+            }
+        }
+
+        let mut scored: Vec<(usize, f32)> = scores.into_iter().enumerate().collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.truncate(limit);
+        if let Some(decimals) = self.score_decimals {
+            let scale = 10f32.powi(decimals as i32);
+            for (_, score) in &mut scored {
+                *score = (*score * scale).round() / scale;
+            }
         }
+        Ok(scored)
     }
 }
 
 impl VectorStore for MemoryVectorStore {
     fn add(&self, text: String, metadata: serde_json::Value) -> Result<(), AgentError> {
-        // Simplified - just store
-        let _ = (text, metadata);
+        let document = self.document(None, text, metadata)?;
+        self.documents.write().unwrap().push(document);
         Ok(())
     }
 
+    /// Embed the batch with `Embedder::embed_batch`, one call per embedder,
+    /// and add it all or nothing
+    fn add_batch(
+        &self,
+        documents: Vec<(String, serde_json::Value)>,
+        cancel: &CancellationToken,
+    ) -> Result<usize, AgentError> {
+        let documents: Vec<(String, serde_json::Value)> = documents
+            .into_iter()
+            .map(|(text, metadata)| (text, self.stamp(metadata)))
+            .collect();
+        let mut groups: std::collections::BTreeMap<Option<usize>, Vec<usize>> =
+            std::collections::BTreeMap::new();
+        for (i, (_, metadata)) in documents.iter().enumerate() {
+            groups
+                .entry(self.embedder_rule(metadata))
+                .or_default()
+                .push(i);
+        }
+        let mut embeddings = vec![Vec::new(); documents.len()];
+        for (rule, members) in groups {
+            let embedder = match rule {
+                Some(rule) => self.document_embedders[rule].embedder.as_ref(),
+                None => self.embedder.as_ref(),
+            };
+            let texts: Vec<&str> = members.iter().map(|&i| documents[i].0.as_str()).collect();
+            for (i, embedding) in members
+                .into_iter()
+                .zip(embedder.embed_batch(&texts, cancel)?)
+            {
+                embeddings[i] = embedding;
+            }
+        }
+
+        let stored = documents
+            .into_iter()
+            .zip(embeddings)
+            .map(|((text, metadata), embedding)| self.stored(None, text, metadata, embedding))
+            .collect::<Result<Vec<_>, _>>()?;
+        let count = stored.len();
+        self.documents.write().unwrap().extend(stored);
+        Ok(count)
+    }
+
     fn search(&self, query: String, limit: usize) -> Result<Vec<(String, f32)>, AgentError> {
-        // Simplified search
-        let _ = query;
-        Ok(vec![("Result".to_string(), 0.9); limit])
+        let documents = self.documents.read().unwrap();
+        Ok(self
+            .rank(&documents, &query, limit)?
+            .into_iter()
+            .map(|(i, score)| (documents[i].text.clone(), score))
+            .collect())
+    }
+
+    fn search_with_embeddings(
+        &self,
+        query: String,
+        limit: usize,
+    ) -> Result<Vec<(String, f32, Vec<f32>)>, AgentError> {
+        let documents = self.documents.read().unwrap();
+        Ok(self
+            .rank(&documents, &query, limit)?
+            .into_iter()
+            .map(|(i, score)| {
+                (
+                    documents[i].text.clone(),
+                    score,
+                    documents[i].embedding.clone(),
+                )
+            })
+            .collect())
+    }
+
+    /// `content_id` within the store's id namespace
+    fn document_id(&self, text: &str) -> String {
+        content_id(self.id_namespace.as_deref(), text)
     }
 }
 
@@ -171,39 +1321,2843 @@ impl Default for MemoryVectorStore {
 pub struct ReActAgent {
     provider: Box<dyn LLMProvider>,
     vector_store: Box<dyn VectorStore>,
+    tools: Vec<Box<dyn Tool>>,
+    /// Tools whose long outputs go to the vector store instead of the context
+    ingested_tools: Vec<String>,
+    /// Tools that only run once the run's approver allows the call
+    approval_required: Vec<String>,
+    retrieval: Option<Retrieval>,
+    retrieval_gate: Option<Box<dyn RetrievalGate>>,
+    /// Run a step's tool calls one at a time, in order
+    sequential_tools: bool,
+    linters: Vec<Box<dyn PromptLinter>>,
+    max_steps: usize,
+    max_thoughts: Option<usize>,
+    metrics: Option<Arc<Metrics>>,
+    /// Shared with `metrics.tools` once metrics are attached
+    tool_usage: Arc<ToolUsage>,
+    inflight: Arc<InflightRegistry>,
+    clock: Arc<dyn Clock>,
+    event_sink: Option<Arc<dyn EventSink>>,
+    timeout: Option<Duration>,
+    /// Longest wait for the next delta of a streaming call
+    inter_token_timeout: Duration,
+    partial_on_timeout: bool,
+    answer_extractor: AnswerExtractor,
+    max_result_chars: Option<usize>,
+    max_prompt_bytes: usize,
+    /// Most tokens one run may consume across its model calls
+    token_budget: Option<u64>,
+    trace_tree: bool,
+    json_patches: bool,
+    /// Re-prompts `execute_json` sends when the answer is not valid JSON
+    json_retries: usize,
 }
 
-impl ReActAgent {
-    pub fn new(provider: Box<dyn LLMProvider>, vector_store: Box<dyn VectorStore>) -> Self {
-        Self { provider, vector_store }
+/// How many vector store hits to retrieve and how much of them to send
+#[derive(Debug, Clone, Copy)]
+struct Retrieval {
+    limit: usize,
+    budget_chars: usize,
+}
+
+/// Turns the model's last message into the bare answer for `AgentResponse.result`
+pub type AnswerExtractor = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Marker the ReAct format puts before the answer
+const FINAL_ANSWER_MARKER: &str = "Final Answer:";
+
+/// Default answer extraction
+///
+/// Returns the text after the last `Final Answer:` marker, or the whole
+/// message when there is none, trimmed either way.
+pub fn extract_final_answer(message: &str) -> String {
+    match message.rfind(FINAL_ANSWER_MARKER) {
+        Some(i) => message[i + FINAL_ANSWER_MARKER.len()..].trim().to_string(),
+        None => message.trim().to_string(),
     }
+}
 
-    pub async fn execute(&self, task: String) -> Result<AgentResponse, AgentError> {
-        let request = AgentRequest {
-            task: task.clone(),
-            model: None,
-            temperature: None,
-        };
-        
-        self.provider.chat(request).await
+/// `answer` parsed as JSON, ignoring a surrounding code fence
+fn parse_json_answer(answer: &str) -> Result<serde_json::Value, serde_json::Error> {
+    let mut json = answer.trim();
+    if let Some(fenced) = json.strip_prefix("```") {
+        json = fenced.strip_prefix("json").unwrap_or(fenced);
+        json = json.strip_suffix("```").unwrap_or(json).trim();
     }
+    serde_json::from_str(json)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Settings for a single agent run
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    /// Headers sent with every provider call of this run only
+    pub headers: HeaderMap,
+    /// Pause after this many model steps and return the run's `RunState`
+    ///
+    /// Only `ReActAgent::resume` can pause; `execute` fails if asked to.
+    pub pause_after: Option<usize>,
+    /// Decides calls of tools that require approval
+    ///
+    /// Without one the run is non-interactive and every such call is denied.
+    pub approver: Option<Arc<dyn ToolApprover>>,
+    /// Language every step of this run answers in (see `AgentRequest::locale`)
+    pub locale: Option<String>,
+    /// Provider every step of this run is routed to (see `ProviderRouter`)
+    pub provider: Option<String>,
+    /// Model every step of this run asks for instead of the provider's own
+    pub model: Option<String>,
+    /// Safety configuration sent with every step (see
+    /// `AgentRequest::safety_settings`)
+    pub safety_settings: Option<serde_json::Value>,
+    /// Stop at the first tool calls the model asks for, without running them
+    ///
+    /// The response lists the calls in `tool_calls` (and as `action`
+    /// thoughts) and has `plan_only` set, for review before a real run.
+    /// A model that answers without tools returns its answer as usual.
+    pub plan_only: bool,
+    /// Run inside a `DEBUG_SPAN` span, for subscribers that log such runs
+    /// at debug level whatever their configured level
+    ///
+    /// Debug events include every provider request body and raw response
+    /// in full, so the prompts of the run end up in the logs. Only the body
+    /// paths the transport's `HeaderRedactor` was given are masked.
+    pub debug: bool,
+    /// Also stream the run's `AgentEvent`s as `StreamEvent::Lifecycle`,
+    /// whether or not the agent has an `EventSink`
+    pub lifecycle_events: bool,
+}
 
-    #[tokio::test]
-    async fn test_openai_provider() {
-        let provider = OpenAIProvider::new("test-key".to_string());
-        let request = AgentRequest {
-            task: "Hello".to_string(),
+impl std::fmt::Debug for RunOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RunOptions")
+            .field("headers", &self.headers)
+            .field("pause_after", &self.pause_after)
+            .field("approver", &self.approver.is_some())
+            .field("locale", &self.locale)
+            .field("provider", &self.provider)
+            .field("model", &self.model)
+            .field("safety_settings", &self.safety_settings)
+            .field("plan_only", &self.plan_only)
+            .field("debug", &self.debug)
+            .field("lifecycle_events", &self.lifecycle_events)
+            .finish()
+    }
+}
+
+/// Ending of a result cut by `ReActAgent::with_max_result_chars`
+pub const TRUNCATION_MARKER: &str = "…";
+
+/// `text` cut to at most `max_chars` characters, ending in the marker if cut
+fn truncate_result(text: String, max_chars: usize) -> (String, bool) {
+    if text.chars().count() <= max_chars {
+        return (text, false);
+    }
+    let keep = max_chars.saturating_sub(TRUNCATION_MARKER.chars().count());
+    let mut cut: String = text.chars().take(keep).collect();
+    cut.push_str(TRUNCATION_MARKER);
+    (cut, true)
+}
+
+/// Response of a run that is not allowed to pause
+fn finished(outcome: RunOutcome) -> Result<AgentResponse, AgentError> {
+    match outcome {
+        RunOutcome::Finished(response) => Ok(response),
+        RunOutcome::Paused(state) => Err(AgentError::ApiError(format!(
+            "run paused after {} steps; use ReActAgent::resume to pause runs",
+            state.step
+        ))),
+    }
+}
+
+/// Name of the span a run with `RunOptions::debug` runs in
+pub const DEBUG_SPAN: &str = "agent_debug_run";
+
+/// Default inter-token timeout of streaming runs
+pub const DEFAULT_INTER_TOKEN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Default ceiling on the assembled prompt, 4 MiB
+pub const DEFAULT_MAX_PROMPT_BYTES: usize = 4 << 20;
+
+/// Longest tool output kept inline for ingested tools, and their chunk size
+const INGEST_CHUNK_CHARS: usize = 1000;
+/// Characters of an ingested output shown to the model
+const INGEST_PREVIEW_CHARS: usize = 200;
+
+/// What a run has produced so far
+struct RunProgress {
+    /// Id shared by the run's events
+    run_id: u64,
+    ttft_ms: Option<u64>,
+    /// Provider of the latest model response
+    provider: String,
+    /// Tool calls a `plan_only` run stopped at
+    planned: Vec<ToolCall>,
+    state: RunState,
+}
+
+impl ReActAgent {
+    pub fn new(provider: Box<dyn LLMProvider>, vector_store: Box<dyn VectorStore>) -> Self {
+        Self {
+            provider,
+            vector_store,
+            tools: Vec::new(),
+            ingested_tools: Vec::new(),
+            approval_required: Vec::new(),
+            retrieval: None,
+            retrieval_gate: None,
+            sequential_tools: false,
+            linters: Vec::new(),
+            max_steps: 10,
+            max_thoughts: None,
+            metrics: None,
+            tool_usage: Arc::new(ToolUsage::new()),
+            inflight: Arc::default(),
+            clock: Arc::new(SystemClock),
+            event_sink: None,
+            timeout: None,
+            inter_token_timeout: DEFAULT_INTER_TOKEN_TIMEOUT,
+            partial_on_timeout: false,
+            answer_extractor: Box::new(extract_final_answer),
+            max_result_chars: None,
+            max_prompt_bytes: DEFAULT_MAX_PROMPT_BYTES,
+            token_budget: None,
+            trace_tree: false,
+            json_patches: false,
+            json_retries: 0,
+        }
+    }
+
+    /// Run the provider's one-time `init`; call before serving requests
+    pub async fn init(&self) -> Result<(), AgentError> {
+        self.provider.init().await
+    }
+
+    pub fn with_tool(mut self, tool: Box<dyn Tool>) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
+    /// Store long outputs of the tool named `name` in the vector store
+    ///
+    /// Outputs over `INGEST_CHUNK_CHARS` characters are chunked and added to
+    /// the store, and the model only sees a short reference to them.
+    pub fn with_ingested_tool(mut self, name: impl Into<String>) -> Self {
+        self.ingested_tools.push(name.into());
+        self
+    }
+
+    /// Ask the run's approver before every call of the tool named `name`
+    ///
+    /// The run emits `StreamEvent::ApprovalRequested` and waits for the
+    /// answer; a denied call is not run and the model observes
+    /// `TOOL_DENIED` instead.
+    pub fn with_approval_required(mut self, name: impl Into<String>) -> Self {
+        self.approval_required.push(name.into());
+        self
+    }
+
+    /// Retrieve up to `limit` chunks for the task from the vector store
+    ///
+    /// Chunks are added to the prompt in rank order while their total stays
+    /// within `budget_chars`; the response reports how many were retrieved,
+    /// how many were used and which were dropped.
+    pub fn with_retrieval(mut self, limit: usize, budget_chars: usize) -> Self {
+        self.retrieval = Some(Retrieval {
+            limit,
+            budget_chars,
+        });
+        self
+    }
+
+    /// Ask `gate` whether a task needs retrieval before searching for it
+    ///
+    /// Skipped runs send no context and report `retrieval_skipped`.
+    pub fn with_retrieval_gate(mut self, gate: Box<dyn RetrievalGate>) -> Self {
+        self.retrieval_gate = Some(gate);
+        self
+    }
+
+    /// Vector store the agent retrieves from and ingests tool output into
+    pub fn vector_store(&self) -> &dyn VectorStore {
+        self.vector_store.as_ref()
+    }
+
+    /// Run tool calls one at a time in the order the model emitted them
+    ///
+    /// By default the calls of one step run concurrently. Sequential calls
+    /// make logs and side effects reproducible, at the cost of latency.
+    pub fn with_sequential_tools(mut self, sequential_tools: bool) -> Self {
+        self.sequential_tools = sequential_tools;
+        self
+    }
+
+    /// Check every assembled prompt with `linter` before it is sent
+    ///
+    /// Warnings are added to the response; errors abort the run with
+    /// `AgentError::Validation`.
+    pub fn with_prompt_linter(mut self, linter: Box<dyn PromptLinter>) -> Self {
+        self.linters.push(linter);
+        self
+    }
+
+    /// Maximum number of model calls in one run
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Keep only the most recent `max_thoughts` thoughts in responses
+    ///
+    /// The thoughts that are cut are still logged at debug level.
+    pub fn with_max_thoughts(mut self, max_thoughts: usize) -> Self {
+        self.max_thoughts = Some(max_thoughts);
+        self
+    }
+
+    /// Record run metrics (such as time to first token) into `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.tool_usage = metrics.tools.clone();
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Call counts, failures and time spent per tool, across all runs
+    pub fn tool_stats(&self) -> std::collections::BTreeMap<String, ToolStats> {
+        self.tool_usage.snapshot()
+    }
+
+    /// Runs in progress right now, oldest first
+    ///
+    /// Runs are listed from the start of `execute` (or `resume`) and removed
+    /// as soon as they finish, fail or are dropped.
+    pub fn inflight(&self) -> Vec<InflightRun> {
+        self.inflight.snapshot(self.clock.now())
+    }
+
+    /// Emit structured events for every run, step and tool call to `sink`
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sink = Some(sink);
+        self
+    }
+
+    /// Measure `duration_ms` and `ttft_ms` with `clock` instead of real time
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Fail runs that take longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Cancel a streaming model call when no delta arrives for `timeout`
+    ///
+    /// The wait for the first delta counts too, and providers without
+    /// native streaming send their only delta once the whole response is
+    /// done, so keep this above their slowest responses. Defaults to
+    /// `DEFAULT_INTER_TOKEN_TIMEOUT`. Stalls fail the run with
+    /// `AgentError::Timeout` carrying the content received so far.
+    pub fn with_inter_token_timeout(mut self, timeout: Duration) -> Self {
+        self.inter_token_timeout = timeout;
+        self
+    }
+
+    /// Return `AgentResponse.trace`, a tree of the run's thoughts and tool calls
+    ///
+    /// Tools that run sub-agents nest those runs' traces under their call
+    /// with `ToolProgressSender::attach_trace`. The flat `thoughts` list is
+    /// returned either way.
+    pub fn with_trace_tree(mut self) -> Self {
+        self.trace_tree = true;
+        self
+    }
+
+    /// Also stream JSON answers as `StreamEvent::Patch` merge patches
+    ///
+    /// For structured output: while a model call streams, the JSON object
+    /// in its content is parsed as far as it goes and every change is sent
+    /// as a patch after the usual `Delta`. Non-JSON content produces no
+    /// patches. Each model call starts again from an empty object.
+    pub fn with_json_patches(mut self) -> Self {
+        self.json_patches = true;
+        self
+    }
+
+    /// Fail steps whose assembled prompt is over `max_bytes`
+    ///
+    /// Counts the bytes of every message sent (retrieved context, history,
+    /// task and tool turns) and returns `AgentError::Validation` before the
+    /// provider is called. A blunt guard against runaway prompts, e.g. from
+    /// huge retrieved documents; defaults to `DEFAULT_MAX_PROMPT_BYTES`.
+    pub fn with_max_prompt_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_prompt_bytes = max_bytes;
+        self
+    }
+
+    /// Abort a run once its model calls have consumed more than `tokens`
+    ///
+    /// Usage is summed over the steps from what the provider reports;
+    /// calls without usage count as zero. The run fails with
+    /// `AgentError::TokenBudgetExceeded`, which carries the thoughts up to
+    /// that step (streaming runs have also sent them). A resumed run keeps
+    /// counting from its `RunState.usage`. Off by default.
+    pub fn with_token_budget(mut self, tokens: u64) -> Self {
+        self.token_budget = Some(tokens);
+        self
+    }
+
+    /// At the timeout, return the last model output with `timed_out` set
+    /// instead of failing
+    pub fn with_partial_on_timeout(mut self) -> Self {
+        self.partial_on_timeout = true;
+        self
+    }
+
+    /// Extract the answer from the model's last message with `extractor`
+    /// instead of `extract_final_answer`, for prompts with other scaffolding
+    pub fn with_answer_extractor(
+        mut self,
+        extractor: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.answer_extractor = Box::new(extractor);
+        self
+    }
+
+    /// Let `execute_json` re-prompt up to `retries` times when the answer
+    /// is not valid JSON
+    ///
+    /// Each retry sends the task, the rejected answer and the parse error
+    /// back to the model with an instruction to reply with JSON only.
+    pub fn with_json_retries(mut self, retries: usize) -> Self {
+        self.json_retries = retries;
+        self
+    }
+
+    /// Cut `AgentResponse.result` to at most `max_chars` characters
+    ///
+    /// A cut result ends in `TRUNCATION_MARKER` and sets `truncated`. Model
+    /// calls also get a `max_tokens` hint of `max_chars / 2`: about twice
+    /// the answer's length in tokens, leaving room for the reasoning that
+    /// comes before it.
+    pub fn with_max_result_chars(mut self, max_chars: usize) -> Self {
+        self.max_result_chars = Some(max_chars);
+        self
+    }
+
+    pub async fn execute(&self, task: String) -> Result<AgentResponse, AgentError> {
+        self.execute_with(task, RunOptions::default()).await
+    }
+
+    /// Execute a task with per-run settings such as pass-through headers
+    pub async fn execute_with(
+        &self,
+        task: String,
+        options: RunOptions,
+    ) -> Result<AgentResponse, AgentError> {
+        finished(self.run(RunState::new(task), &options, None).await?)
+    }
+
+    /// Execute a task whose answer must be JSON, returning the parsed value
+    ///
+    /// A code fence around the answer is ignored. An answer that does not
+    /// parse is re-prompted as set by `with_json_retries`; once retries run
+    /// out the run fails with `AgentError::ParseError` holding the parse
+    /// error and the last raw answer.
+    ///
+    /// Every retry is a run of its own that continues the conversation, so
+    /// it can call tools and is tracked like any run. The token budget
+    /// counts the usage of all attempts together, and the agent's timeout
+    /// covers the first run and its retries.
+    pub async fn execute_json(
+        &self,
+        task: String,
+        options: RunOptions,
+    ) -> Result<serde_json::Value, AgentError> {
+        let attempts = self.json_attempts(task, &options);
+        let Some(timeout) = self.timeout else {
+            return attempts.await;
+        };
+        tokio::time::timeout(timeout, attempts)
+            .await
+            .unwrap_or_else(|_| {
+                Err(AgentError::ApiError(format!(
+                    "agent timed out after {} ms",
+                    timeout.as_millis()
+                )))
+            })
+    }
+
+    /// The runs of `execute_json`, re-prompting after each unparsable answer
+    async fn json_attempts(
+        &self,
+        task: String,
+        options: &RunOptions,
+    ) -> Result<serde_json::Value, AgentError> {
+        let mut turns = Vec::new();
+        let mut usage = None;
+        let mut retries = 0;
+        loop {
+            let state = RunState {
+                turns: turns.clone(),
+                usage,
+                ..RunState::new(task.as_str())
+            };
+            let response = finished(self.run(state, options, None).await?)?;
+            usage = response.usage;
+            let answer = response.result;
+            let error = match parse_json_answer(&answer) {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            if retries == self.json_retries {
+                return Err(AgentError::ParseError(format!(
+                    "answer is not valid JSON after {} retries: {}; last output: {}",
+                    retries, error, answer
+                )));
+            }
+            retries += 1;
+            tracing::debug!(retry = retries, %error, "re-prompting for JSON");
+            turns.push(Message::assistant(answer));
+            turns.push(Message::user(format!(
+                "Your reply was not valid JSON ({}). Reply again with only the JSON value: \
+                 no prose and no code fences.",
+                error
+            )));
+        }
+    }
+
+    /// Continue a run from `state`, e.g. one paused by `options.pause_after`
+    ///
+    /// Start a fresh resumable run with `RunState::new(task)`. The response's
+    /// `duration_ms` only covers this call.
+    pub async fn resume(
+        &self,
+        state: RunState,
+        options: RunOptions,
+    ) -> Result<RunOutcome, AgentError> {
+        self.run(state, &options, None).await
+    }
+
+    /// Execute a task, streaming content, thoughts and tool progress as they happen
+    ///
+    /// `StreamEvent::Accepted` is sent first, before the provider is called.
+    /// The final response is both returned and sent as `StreamEvent::Done`;
+    /// a failure is sent as `StreamEvent::Error`.
+    pub async fn execute_stream(
+        &self,
+        task: String,
+        events: UnboundedSender<StreamEvent>,
+    ) -> Result<AgentResponse, AgentError> {
+        self.execute_stream_with(task, RunOptions::default(), events)
+            .await
+    }
+
+    /// `execute_stream` with per-run settings
+    pub async fn execute_stream_with(
+        &self,
+        task: String,
+        options: RunOptions,
+        events: UnboundedSender<StreamEvent>,
+    ) -> Result<AgentResponse, AgentError> {
+        let outcome = self
+            .run(RunState::new(task), &options, Some(&events))
+            .await
+            .and_then(finished);
+        match outcome {
+            Ok(response) => {
+                if let (Some(metrics), Some(ttft_ms)) = (&self.metrics, response.ttft_ms) {
+                    metrics.ttft_ms.observe(ttft_ms);
+                }
+                let _ = events.send(StreamEvent::Done(response.clone()));
+                Ok(response)
+            }
+            Err(e) => {
+                let _ = events.send(StreamEvent::Error {
+                    message: e.to_string(),
+                });
+                Err(e)
+            }
+        }
+    }
+
+    async fn run(
+        &self,
+        state: RunState,
+        options: &RunOptions,
+        events: Option<&UnboundedSender<StreamEvent>>,
+    ) -> Result<RunOutcome, AgentError> {
+        let run_id = events::next_run_id();
+        let _inflight = self.inflight.start(
+            run_id,
+            self.clock.now(),
+            options.provider.clone(),
+            options.model.clone(),
+        );
+        if let Some(events) = events {
+            let _ = events.send(StreamEvent::Accepted { run_id });
+        }
+        let lifecycle = events.filter(|_| options.lifecycle_events);
+        self.emit(
+            run_id,
+            AgentEventKind::RequestReceived {
+                task: state.task.clone(),
+            },
+            lifecycle,
+        );
+
+        let inner = self.run_inner(state, options, events, run_id);
+        let outcome = if options.debug {
+            inner
+                .instrument(tracing::info_span!(DEBUG_SPAN, run_id))
+                .await
+        } else {
+            inner.await
+        };
+        self.emit(
+            run_id,
+            match &outcome {
+                Ok(RunOutcome::Finished(response)) => AgentEventKind::Response {
+                    duration_ms: response.duration_ms,
+                    thoughts: response.thoughts.len(),
+                    warnings: response.warnings.len(),
+                    timed_out: response.timed_out,
+                },
+                Ok(RunOutcome::Paused(state)) => AgentEventKind::Paused { step: state.step },
+                Err(e) => AgentEventKind::Failed {
+                    error: e.to_string(),
+                },
+            },
+            lifecycle,
+        );
+        outcome
+    }
+
+    async fn run_inner(
+        &self,
+        state: RunState,
+        options: &RunOptions,
+        events: Option<&UnboundedSender<StreamEvent>>,
+        run_id: u64,
+    ) -> Result<RunOutcome, AgentError> {
+        let start = self.clock.now();
+        let mut progress = RunProgress {
+            run_id,
+            ttft_ms: None,
+            provider: String::new(),
+            planned: Vec::new(),
+            state,
+        };
+
+        let (result, timed_out) = match self.timeout {
+            None => (
+                self.steps(options, events, start, &mut progress).await?,
+                false,
+            ),
+            Some(timeout) => {
+                match tokio::time::timeout(
+                    timeout,
+                    self.steps(options, events, start, &mut progress),
+                )
+                .await
+                {
+                    Ok(result) => (result?, false),
+                    Err(_) if self.partial_on_timeout => {
+                        progress.state.warnings.push(format!(
+                            "timed out after {} ms, returned the last model output",
+                            timeout.as_millis()
+                        ));
+                        (Some(std::mem::take(&mut progress.state.last_result)), true)
+                    }
+                    Err(_) => {
+                        return Err(AgentError::ApiError(format!(
+                            "agent timed out after {} ms",
+                            timeout.as_millis()
+                        )))
+                    }
+                }
+            }
+        };
+        let Some(result) = result else {
+            return Ok(RunOutcome::Paused(progress.state));
+        };
+
+        let mut state = progress.state;
+        let dropped = self.truncate_thoughts(&mut state.thoughts);
+        if dropped > 0 {
+            state.warnings.push(format!(
+                "{} older thoughts were dropped from the response",
+                dropped
+            ));
+        }
+        let mut result = (self.answer_extractor)(&result);
+        let mut truncated = false;
+        if let Some(max_chars) = self.max_result_chars {
+            (result, truncated) = truncate_result(result, max_chars);
+        }
+        Ok(RunOutcome::Finished(AgentResponse {
+            result,
+            thoughts: state.thoughts,
+            duration_ms: self.elapsed_ms(start),
+            thoughts_truncated: dropped > 0,
+            ttft_ms: progress.ttft_ms,
+            warnings: state.warnings,
+            timed_out,
+            retrieved_chunks: state.retrieved_chunks,
+            retrieval_skipped: state.retrieval_skipped,
+            used_chunks: state.context.len(),
+            dropped_chunks: state.dropped_chunks,
+            truncated,
+            provider: progress.provider,
+            trace: self.trace_tree.then_some(state.trace),
+            tool_calls: progress.planned,
+            plan_only: options.plan_only,
+            usage: state.usage,
+        }))
+    }
+
+    /// Run model and tool steps until the model answers, returning its answer
+    ///
+    /// Returns `None` when the run pauses at `options.pause_after`.
+    async fn steps(
+        &self,
+        options: &RunOptions,
+        events: Option<&UnboundedSender<StreamEvent>>,
+        start: Instant,
+        progress: &mut RunProgress,
+    ) -> Result<Option<String>, AgentError> {
+        let tools: Vec<ToolSpec> = self.tools.iter().map(|tool| tool.spec()).collect();
+        let lifecycle = events.filter(|_| options.lifecycle_events);
+        let state = &mut progress.state;
+        let mut taken = 0;
+        if state.step == 0 {
+            self.retrieve(state).await;
+        }
+        let context = context::render(&state.context);
+
+        let record = |thoughts: &mut Vec<Thought>, thought: Thought| {
+            if let Some(events) = events {
+                let _ = events.send(StreamEvent::Thought(thought.clone()));
+            }
+            thoughts.push(thought);
+        };
+
+        while state.step < self.max_steps {
+            if options.pause_after.is_some_and(|limit| taken >= limit) {
+                return Ok(None);
+            }
+            taken += 1;
+            state.step += 1;
+            self.emit(
+                progress.run_id,
+                AgentEventKind::Step { step: state.step },
+                lifecycle,
+            );
+            self.inflight.set_step(progress.run_id, state.step);
+
+            let request = AgentRequest {
+                task: format!("{}{}{}", context, state.task, state.scratchpad),
+                turns: state.turns.clone(),
+                tools: tools.clone(),
+                headers: options.headers.clone(),
+                locale: options.locale.clone(),
+                provider: options.provider.clone(),
+                model: options.model.clone(),
+                safety_settings: options.safety_settings.clone(),
+                max_tokens: self.max_result_chars.map(|chars| (chars / 2).max(1) as u32),
+                ..Default::default()
+            };
+            self.lint(&request.task, &mut state.warnings)?;
+            self.check_prompt_size(&request)?;
+            let response = match events {
+                None => self.provider.chat(request).await?,
+                Some(events) => {
+                    self.chat_stream(request, events, start, &mut progress.ttft_ms)
+                        .await?
+                }
+            };
+            progress.provider = response.provider;
+            for thought in response.thoughts {
+                if self.trace_tree {
+                    state.trace.push(TraceNode::Thought {
+                        thought_type: thought.thought_type.clone(),
+                        content: thought.content.clone(),
+                    });
+                }
+                record(&mut state.thoughts, thought);
+            }
+            state.warnings.extend(response.warnings);
+            if let Some(usage) = response.usage {
+                state.usage.get_or_insert_with(Usage::default).add(usage);
+            }
+            self.check_token_budget(state)?;
+
+            if response.tool_calls.is_empty() {
+                return Ok(Some(response.result));
+            }
+            if !response.result.is_empty() {
+                state.last_result = response.result.clone();
+            }
+
+            if options.plan_only {
+                for call in &response.tool_calls {
+                    record(
+                        &mut state.thoughts,
+                        Thought {
+                            thought_type: "action".to_string(),
+                            content: format!("{}({})", call.name, self.loggable_arguments(call)),
+                        },
+                    );
+                }
+                progress.planned = response.tool_calls;
+                return Ok(Some(response.result));
+            }
+            state.turns.push(Message::tool_calls(
+                response.result.as_str(),
+                response.tool_calls.clone(),
+            ));
+
+            for call in &response.tool_calls {
+                let arguments = self.loggable_arguments(call);
+                tracing::debug!(tool = %call.name, %arguments, "calling tool");
+                let action = format!("{}({})", call.name, arguments);
+                self.emit(
+                    progress.run_id,
+                    AgentEventKind::ToolCall {
+                        tool: call.name.clone(),
+                        arguments,
+                    },
+                    lifecycle,
+                );
+                record(
+                    &mut state.thoughts,
+                    Thought {
+                        thought_type: "action".to_string(),
+                        content: action,
+                    },
+                );
+            }
+
+            let observations = if self.sequential_tools {
+                let mut observations = Vec::with_capacity(response.tool_calls.len());
+                for call in &response.tool_calls {
+                    observations.push(self.call_tool(call, options, events).await);
+                }
+                observations
+            } else {
+                // Tool calls from the same step run concurrently
+                futures::future::join_all(
+                    response
+                        .tool_calls
+                        .iter()
+                        .map(|call| self.call_tool(call, options, events)),
+                )
+                .await
+            };
+
+            for (call, (outcome, children)) in response.tool_calls.iter().zip(observations) {
+                let (observation, is_error) = match outcome {
+                    Ok(output) => (self.ingest_output(call, output, &mut state.warnings), false),
+                    Err(failure) => {
+                        state.warnings.push(format!(
+                            "tool {} failed, continued without it: {}",
+                            call.name, failure
+                        ));
+                        (failure, true)
+                    }
+                };
+                state.turns.push(Message::tool_result(ToolResult {
+                    call_id: call.id.clone(),
+                    name: call.name.clone(),
+                    content: observation.clone(),
+                    is_error,
+                }));
+                if self.trace_tree {
+                    state.trace.push(TraceNode::ToolCall {
+                        name: call.name.clone(),
+                        arguments: self.loggable_arguments(call),
+                        observation: observation.clone(),
+                        children,
+                    });
+                }
+                record(
+                    &mut state.thoughts,
+                    Thought {
+                        thought_type: "observation".to_string(),
+                        content: observation,
+                    },
+                );
+            }
+        }
+
+        Err(AgentError::ApiError(format!(
+            "agent did not finish within {} steps",
+            self.max_steps
+        )))
+    }
+
+    /// Stream one model call, forwarding deltas to `events`
+    ///
+    /// Cancels the call with `AgentError::Timeout` when no delta arrives
+    /// within the inter-token timeout.
+    async fn chat_stream(
+        &self,
+        request: AgentRequest,
+        events: &UnboundedSender<StreamEvent>,
+        start: Instant,
+        ttft_ms: &mut Option<u64>,
+    ) -> Result<AgentResponse, AgentError> {
+        let (deltas, mut received) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let chat = self.provider.chat_stream(request, deltas);
+        tokio::pin!(chat);
+
+        let mut partial = String::new();
+        let mut patches = self.json_patches.then(PatchStream::default);
+        let mut forward = |content: String| {
+            ttft_ms.get_or_insert_with(|| self.elapsed_ms(start));
+            partial.push_str(&content);
+            let patch = patches.as_mut().and_then(|patches| patches.push(&content));
+            let _ = events.send(StreamEvent::Delta { content });
+            if let Some(patch) = patch {
+                let _ = events.send(StreamEvent::Patch { patch });
+            }
+        };
+        let response = loop {
+            tokio::select! {
+                response = &mut chat => break response,
+                delta = tokio::time::timeout(self.inter_token_timeout, received.recv()) => {
+                    match delta {
+                        Ok(Some(content)) => forward(content),
+                        // The provider is done sending
+                        Ok(None) => break (&mut chat).await,
+                        Err(_) => {
+                            return Err(AgentError::Timeout {
+                                message: format!(
+                                    "no token within {} ms",
+                                    self.inter_token_timeout.as_millis()
+                                ),
+                                partial,
+                            })
+                        }
+                    }
+                }
+            }
+        };
+        while let Ok(content) = received.try_recv() {
+            forward(content);
+        }
+        response
+    }
+
+    /// Fail once the run has used more than its `token_budget`
+    fn check_token_budget(&self, state: &RunState) -> Result<(), AgentError> {
+        let Some(budget) = self.token_budget else {
+            return Ok(());
+        };
+        let used = state.usage.map_or(0, |usage| usage.total());
+        if used > budget {
+            return Err(AgentError::TokenBudgetExceeded {
+                used,
+                budget,
+                steps: state.step,
+                thoughts: state.thoughts.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fail with `AgentError::Validation` if `request` is over `max_prompt_bytes`
+    fn check_prompt_size(&self, request: &AgentRequest) -> Result<(), AgentError> {
+        let bytes: usize = request
+            .messages()
+            .iter()
+            .map(|message| message.content.len())
+            .sum();
+        if bytes > self.max_prompt_bytes {
+            return Err(AgentError::Validation(format!(
+                "prompt is {} bytes, over the limit of {}",
+                bytes, self.max_prompt_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run the prompt linters, failing on the first error
+    fn lint(&self, prompt: &str, warnings: &mut Vec<String>) -> Result<(), AgentError> {
+        for issue in self.linters.iter().flat_map(|linter| linter.lint(prompt)) {
+            match issue {
+                // Later steps resend the same prompt, so report each warning once
+                LintIssue::Warning(warning) => {
+                    if !warnings.contains(&warning) {
+                        warnings.push(warning);
+                    }
+                }
+                LintIssue::Error(error) => return Err(AgentError::Validation(error)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill the run's context from the vector store
+    ///
+    /// A failed search is a warning; the run continues without context.
+    async fn retrieve(&self, state: &mut RunState) {
+        let Some(retrieval) = self.retrieval else {
+            return;
+        };
+        if let Some(gate) = &self.retrieval_gate {
+            if !gate.should_retrieve(&state.task).await {
+                tracing::debug!(task = %state.task, "relevance gate skipped retrieval");
+                state.retrieval_skipped = true;
+                return;
+            }
+        }
+        let hits = match self
+            .vector_store
+            .search(state.task.clone(), retrieval.limit)
+        {
+            Ok(hits) => hits,
+            Err(e) => {
+                state.warnings.push(format!(
+                    "retrieval failed, continued without context: {}",
+                    e
+                ));
+                return;
+            }
+        };
+
+        state.retrieved_chunks = hits.len();
+        let packed = context::pack(
+            hits.into_iter().map(|(text, _)| text).collect(),
+            retrieval.budget_chars,
+        );
+        state.context = packed.used;
+        state.dropped_chunks = packed.dropped;
+    }
+
+    /// Observation for a successful tool output
+    ///
+    /// Long outputs of ingested tools are chunked into the vector store and
+    /// replaced by a reference. If the store rejects them the full output is
+    /// kept.
+    fn ingest_output(&self, call: &ToolCall, output: String, warnings: &mut Vec<String>) -> String {
+        if !self.ingested_tools.contains(&call.name) || output.chars().count() <= INGEST_CHUNK_CHARS
+        {
+            return output;
+        }
+
+        let chunks = chunking::chunk_text(&output, INGEST_CHUNK_CHARS);
+        let count = chunks.len();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let metadata = serde_json::json!({
+                "source": "tool",
+                "tool": call.name,
+                "call_id": call.id,
+                "chunk": i,
+            });
+            if let Err(e) = self.vector_store.add(chunk, metadata) {
+                warnings.push(format!(
+                    "could not store output of tool {}, kept it inline: {}",
+                    call.name, e
+                ));
+                return output;
+            }
+        }
+
+        let preview: String = output.chars().take(INGEST_PREVIEW_CHARS).collect();
+        format!(
+            "Stored {} characters of output in {} chunks in the vector store. Preview: {}...",
+            output.chars().count(),
+            count,
+            preview
+        )
+    }
+
+    /// Milliseconds on the agent's clock since `start`
+    fn elapsed_ms(&self, start: Instant) -> u64 {
+        self.clock
+            .now()
+            .saturating_duration_since(start)
+            .as_millis() as u64
+    }
+
+    /// Send an event to the agent's sink and, if given, to the run's stream
+    fn emit(
+        &self,
+        run_id: u64,
+        kind: AgentEventKind,
+        stream: Option<&UnboundedSender<StreamEvent>>,
+    ) {
+        if self.event_sink.is_none() && stream.is_none() {
+            return;
+        }
+        let event = AgentEvent::new(run_id, kind);
+        if let Some(stream) = stream {
+            let _ = stream.send(StreamEvent::Lifecycle {
+                event: event.clone(),
+            });
+        }
+        if let Some(sink) = &self.event_sink {
+            sink.emit(event);
+        }
+    }
+
+    /// Drop the oldest thoughts beyond `max_thoughts`, returning how many were dropped
+    fn truncate_thoughts(&self, thoughts: &mut Vec<Thought>) -> usize {
+        let Some(max_thoughts) = self.max_thoughts else {
+            return 0;
+        };
+        if thoughts.len() <= max_thoughts {
+            return 0;
+        }
+
+        let dropped = thoughts.len() - max_thoughts;
+        tracing::debug!(count = thoughts.len(), dropped, "truncating thought trace");
+        for thought in thoughts.drain(..dropped) {
+            tracing::debug!(
+                thought_type = %thought.thought_type,
+                content = %thought.content,
+                "dropped thought"
+            );
+        }
+        dropped
+    }
+
+    /// Arguments of `call` with the tool's sensitive arguments masked
+    fn loggable_arguments(&self, call: &ToolCall) -> serde_json::Value {
+        match self.tools.iter().find(|tool| tool.name() == call.name) {
+            Some(tool) => redact_json(&call.arguments, tool.sensitive_arguments()),
+            None => call.arguments.clone(),
+        }
+    }
+
+    /// Run one tool call; a failure is returned as the observation for the model
+    async fn call_tool(
+        &self,
+        call: &ToolCall,
+        options: &RunOptions,
+        events: Option<&UnboundedSender<StreamEvent>>,
+    ) -> (Result<String, String>, Vec<TraceNode>) {
+        let Some(tool) = self.tools.iter().find(|tool| tool.name() == call.name) else {
+            return (Err(format!("Unknown tool: {}", call.name)), Vec::new());
+        };
+
+        if self.approval_required.contains(&call.name) {
+            if let Some(events) = events {
+                let _ = events.send(StreamEvent::ApprovalRequested { call: call.clone() });
+            }
+            let approved = match &options.approver {
+                Some(approver) => approver.approve(call).await,
+                None => false,
+            };
+            if !approved {
+                return (Ok(TOOL_DENIED.to_string()), Vec::new());
+            }
+        }
+
+        let progress = ToolProgressSender::new(&call.name, events.cloned());
+        let start = self.clock.now();
+        let result = tool.call(call.arguments.clone(), &progress).await;
+        self.tool_usage.record(
+            &call.name,
+            result.is_ok(),
+            self.clock.now().saturating_duration_since(start),
+        );
+        (
+            result.map_err(|e| format!("Tool error: {}", e)),
+            progress.take_trace(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_openai_provider() {
+        let transport = Arc::new(MockTransport::new().with_json(
+            200,
+            serde_json::json!({
+                "choices": [{"message": {"role": "assistant", "content": "Hello! How can I help?"}}],
+            }),
+        ));
+        let provider = OpenAIProvider::new("test-key".to_string())
+            .with_base_url("http://openai.test/v1")
+            .with_transport(transport.clone());
+        let request = AgentRequest {
+            task: "Hello".to_string(),
+            model: None,
+            temperature: None,
+            ..Default::default()
+        };
+        
+        let response = provider.chat(request).await.unwrap();
+        assert!(response.result.contains("Hello"));
+
+        let sent = transport.requests();
+        assert_eq!(sent[0].url, "http://openai.test/v1/chat/completions");
+        assert_eq!(sent[0].headers["authorization"], "Bearer test-key");
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_without_done_finishes_run() {
+        let events: String = ["Rust ", "is fast"]
+            .iter()
+            .map(|content| {
+                let chunk = serde_json::json!({"choices": [{"delta": {"content": content}}]});
+                format!("data: {}\n\n", chunk)
+            })
+            .collect();
+        let transport = Arc::new(MockTransport::new().with_response(HttpResponse {
+            status: 200,
+            headers: HeaderMap::new(),
+            body: events.into_bytes(),
+        }));
+        let provider = OpenAIProvider::new("test-key".to_string())
+            .with_transport(transport.clone())
+            .with_streaming();
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let response = agent.execute_stream("Hi".to_string(), tx).await.unwrap();
+        assert_eq!(response.result, "Rust is fast");
+        let mut names = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            names.push(event.name());
+        }
+        assert_eq!(names, ["accepted", "delta", "delta", "done"]);
+
+        let sent: serde_json::Value =
+            serde_json::from_slice(&transport.requests()[0].body).unwrap();
+        assert_eq!(sent["stream"], true);
+    }
+
+    /// Records, for every event, whether it happened inside a `DEBUG_SPAN`
+    struct DebugScopeRecorder(Arc<std::sync::Mutex<Vec<bool>>>);
+
+    impl<S> tracing_subscriber::Layer<S> for DebugScopeRecorder
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            context: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let in_debug = context
+                .event_scope(event)
+                .is_some_and(|mut scope| scope.any(|span| span.name() == DEBUG_SPAN));
+            self.0.lock().unwrap().push(in_debug);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_debug_runs_log_inside_debug_span() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let scopes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(DebugScopeRecorder(scopes.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let reply = serde_json::json!({"choices": [{"message": {"content": "Hi"}}]});
+        let transport = Arc::new(
+            MockTransport::new()
+                .with_json(200, reply.clone())
+                .with_json(200, reply),
+        );
+        let provider = OpenAIProvider::new("test-key".to_string()).with_transport(transport);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()));
+
+        let debug = RunOptions {
+            debug: true,
+            ..Default::default()
+        };
+        agent
+            .execute_with("Hello".to_string(), debug)
+            .await
+            .unwrap();
+        let debugged = std::mem::take(&mut *scopes.lock().unwrap());
+        // The request body and the raw response, at least
+        assert!(debugged.len() >= 2);
+        assert!(debugged.iter().all(|&in_debug| in_debug));
+
+        agent.execute("Hello".to_string()).await.unwrap();
+        assert!(scopes.lock().unwrap().iter().all(|&in_debug| !in_debug));
+    }
+
+    #[tokio::test]
+    async fn test_request_headers_override_for_one_call() {
+        let reply = serde_json::json!({"choices": [{"message": {"content": "Hi"}}]});
+        let transport = Arc::new(
+            MockTransport::new()
+                .with_json(200, reply.clone())
+                .with_json(200, reply),
+        );
+        let provider = OpenAIProvider::new("server-key".to_string())
+            .with_name("ollama")
+            .with_transport(transport.clone());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer tenant-key"));
+        headers.insert("x-tenant-id", HeaderValue::from_static("acme"));
+        let tenant = AgentRequest {
+            task: "Hello".to_string(),
+            headers,
+            ..Default::default()
+        };
+        assert_eq!(provider.chat(tenant).await.unwrap().provider, "ollama");
+        provider
+            .chat(AgentRequest {
+                task: "Hello".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let sent = transport.requests();
+        assert_eq!(sent[0].headers["authorization"], "Bearer tenant-key");
+        assert_eq!(sent[0].headers["x-tenant-id"], "acme");
+        assert_eq!(sent[1].headers["authorization"], "Bearer server-key");
+        assert!(sent[1].headers.get("x-tenant-id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_provider_through_transport() {
+        let transport = Arc::new(
+            MockTransport::new()
+                .with_json(
+                    200,
+                    serde_json::json!({"content": [{"type": "text", "text": "Hi there"}]}),
+                )
+                .with_json(
+                    401,
+                    serde_json::json!({"error": {"type": "authentication_error"}}),
+                ),
+        );
+        let provider =
+            AnthropicProvider::new("test-key".to_string()).with_transport(transport.clone());
+        let request = AgentRequest {
+            task: "Hello".to_string(),
+            ..Default::default()
+        };
+
+        let response = provider.chat(request.clone()).await.unwrap();
+        assert_eq!(response.result, "Hi there");
+        assert_eq!(response.provider, "anthropic");
+        assert!(response.tool_calls.is_empty());
+
+        let sent = transport.requests();
+        assert_eq!(sent[0].url, "https://api.anthropic.com/v1/messages");
+        assert_eq!(sent[0].headers["x-api-key"], "test-key");
+        assert_eq!(sent[0].headers["anthropic-version"], ANTHROPIC_VERSION);
+        let body: serde_json::Value = serde_json::from_slice(&sent[0].body).unwrap();
+        assert_eq!(body, provider.build_request(&request));
+
+        let error = provider.chat(request).await.unwrap_err();
+        assert!(matches!(error, AgentError::Unauthorized(_)));
+    }
+
+    #[tokio::test]
+    async fn test_validate_provider_config() {
+        OpenAIProvider::new("sk-test".to_string())
+            .validate()
+            .await
+            .unwrap();
+        AnthropicProvider::new("sk-ant".to_string())
+            .validate()
+            .await
+            .unwrap();
+
+        let invalid = [
+            OpenAIProvider::new(" ".to_string()),
+            OpenAIProvider::new("sk-test".to_string()).with_base_url("localhost:8080"),
+            OpenAIProvider::new("sk-test".to_string()).with_base_url("ftp://example.com"),
+            OpenAIProvider::new("sk-test".to_string()).with_model(String::new()),
+        ];
+        for provider in invalid {
+            assert!(matches!(
+                provider.validate().await,
+                Err(AgentError::Validation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_providers_build_requests_with_their_family() {
+        let request = AgentRequest {
+            task: "Hello".to_string(),
+            ..Default::default()
+        };
+
+        let openai = OpenAIProvider::new("test-key".to_string()).build_request(&request);
+        assert_eq!(openai["model"], "gpt-4");
+        assert!(openai.get("max_tokens").is_none());
+
+        let anthropic = AnthropicProvider::new("test-key".to_string()).build_request(&request);
+        assert_eq!(anthropic["model"], "claude-3-sonnet");
+        assert_eq!(anthropic["messages"][0]["content"], "Hello");
+
+        let gateway = OpenAIProvider::new("test-key".to_string())
+            .with_model("claude-3-haiku".to_string())
+            .with_family(Box::new(AnthropicFamily))
+            .build_request(&request);
+        assert_eq!(gateway["model"], "claude-3-haiku");
+        assert_eq!(gateway["max_tokens"], 4096);
+    }
+
+    #[tokio::test]
+    async fn test_run_safety_settings_reach_gemini_request() {
+        let transport = Arc::new(MockTransport::new().with_json(
+            200,
+            serde_json::json!({"candidates": [{"content": {"parts": [{"text": "Hi"}]}}]}),
+        ));
+        let provider = GeminiProvider::new("test-key".to_string())
+            .with_model("gemini-1.5-flash".to_string())
+            .with_transport(transport.clone());
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()));
+
+        let settings = serde_json::json!([
+            {"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_ONLY_HIGH"},
+        ]);
+        let options = RunOptions {
+            safety_settings: Some(settings.clone()),
+            ..Default::default()
+        };
+        let response = agent
+            .execute_with("Hello".to_string(), options)
+            .await
+            .unwrap();
+        assert_eq!(response.result, "Hi");
+        assert_eq!(response.provider, "gemini");
+
+        let request = &transport.requests()[0];
+        assert_eq!(
+            request.url,
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-flash:generateContent"
+        );
+        assert_eq!(request.headers["x-goog-api-key"], "test-key");
+        assert!(request.headers.get(AUTHORIZATION).is_none());
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["safetySettings"], settings);
+        assert!(body.get("model").is_none());
+    }
+
+    #[test]
+    fn test_canonical_hash_ignores_order_and_headers() {
+        let parse = |json: &str| serde_json::from_str::<AgentRequest>(json).unwrap();
+        let request = parse(
+            r#"{"task": "Hi", "model": "gpt-4", "safety_settings": {"a": 1, "b": [true, null]}}"#,
+        );
+        let reordered = parse(
+            r#"{"safety_settings": {"b": [true, null], "a": 1}, "model": "gpt-4", "task": "Hi"}"#,
+        );
+        assert_eq!(request.canonical_hash(), reordered.canonical_hash());
+
+        let mut with_headers = request.clone();
+        with_headers
+            .headers
+            .insert("x-request-id", HeaderValue::from_static("abc-123"));
+        assert_eq!(request.canonical_hash(), with_headers.canonical_hash());
+
+        let mut other = request.clone();
+        other.task = "Hello".to_string();
+        assert_ne!(request.canonical_hash(), other.canonical_hash());
+    }
+
+    #[test]
+    fn test_canonical_hash_ignores_non_semantic_fields() {
+        let request = AgentRequest {
+            task: "Hi".to_string(),
+            ..Default::default()
+        };
+        let debugged = AgentRequest {
+            debug: true,
+            ..request.clone()
+        };
+        assert_eq!(request.canonical_hash(), debugged.canonical_hash());
+        let routed = AgentRequest {
+            provider: Some("anthropic".to_string()),
+            ..request.clone()
+        };
+        assert_eq!(request.canonical_hash(), routed.canonical_hash());
+    }
+
+    #[test]
+    fn test_locale_becomes_system_instruction() {
+        let mut request = AgentRequest {
+            task: "Hello".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(request.messages(), [Message::user("Hello")]);
+
+        request.locale = Some("French".to_string());
+        assert_eq!(
+            request.messages(),
+            [
+                Message::system("Respond in French."),
+                Message::user("Hello")
+            ]
+        );
+        let anthropic = AnthropicProvider::new("test-key".to_string()).build_request(&request);
+        assert_eq!(anthropic["system"], "Respond in French.");
+    }
+
+    /// Provider that answers after a delay and records whether it finished
+    struct SlowProvider {
+        delay: Duration,
+        finished: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl LLMProvider for SlowProvider {
+        async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+            tokio::time::sleep(self.delay).await;
+            self.finished.store(true, Ordering::SeqCst);
+            Ok(AgentResponse {
+                result: format!("Slow response for: {}", request.task),
+                thoughts: Vec::new(),
+                duration_ms: self.delay.as_millis() as u64,
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_race_returns_fastest_and_cancels_rest() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let providers: Vec<Box<dyn LLMProvider>> = vec![
+            Box::new(SlowProvider {
+                delay: Duration::from_millis(200),
+                finished: finished.clone(),
+            }),
+            Box::new(
+                ChaosProvider::new(Box::new(MockProvider::new()))
+                    .with_latency(Duration::from_millis(10)),
+            ),
+        ];
+        let request = AgentRequest {
+            task: "Hello".to_string(),
             model: None,
             temperature: None,
+            ..Default::default()
+        };
+
+        let response = race(&providers, request).await.unwrap();
+        assert!(response.result.starts_with("Mock"));
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+
+    /// Provider whose first token comes early and the rest much later
+    struct SlowStreamingProvider;
+
+    #[async_trait]
+    impl LLMProvider for SlowStreamingProvider {
+        async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+            self.chat_stream(request, tokio::sync::mpsc::unbounded_channel().0)
+                .await
+        }
+
+        async fn chat_stream(
+            &self,
+            _request: AgentRequest,
+            deltas: UnboundedSender<String>,
+        ) -> Result<AgentResponse, AgentError> {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let _ = deltas.send("Streamed ".to_string());
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            let _ = deltas.send("answer".to_string());
+            Ok(AgentResponse {
+                result: "Streamed answer".to_string(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_race_stream_is_won_by_first_token() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let providers: Vec<Box<dyn LLMProvider>> = vec![
+            // Finishes first, but its only token comes with the full response
+            Box::new(SlowProvider {
+                delay: Duration::from_millis(60),
+                finished: finished.clone(),
+            }),
+            Box::new(SlowStreamingProvider),
+        ];
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let response = race_stream(&providers, AgentRequest::default(), tx)
+            .await
+            .unwrap();
+        assert_eq!(response.result, "Streamed answer");
+        let mut streamed = Vec::new();
+        while let Ok(delta) = rx.try_recv() {
+            streamed.push(delta);
+        }
+        assert_eq!(streamed, ["Streamed ", "answer"]);
+        // The loser was dropped at the first token, before it finished
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_race_skips_failures() {
+        let providers: Vec<Box<dyn LLMProvider>> = vec![
+            Box::new(
+                ChaosProvider::new(Box::new(SlowProvider {
+                    delay: Duration::ZERO,
+                    finished: Arc::new(AtomicBool::new(false)),
+                }))
+                .with_failure_rate(1.0),
+            ),
+            Box::new(MockProvider::new()),
+        ];
+        let request = AgentRequest {
+            task: "Hello".to_string(),
+            model: None,
+            temperature: None,
+            ..Default::default()
+        };
+
+        let response = race(&providers, request.clone()).await.unwrap();
+        assert!(response.result.starts_with("Mock"));
+        assert!(race(&[], request).await.is_err());
+    }
+
+    /// Provider that replays a fixed script of responses
+    struct ScriptedProvider {
+        responses: std::sync::Mutex<std::collections::VecDeque<AgentResponse>>,
+    }
+
+    impl ScriptedProvider {
+        fn new(responses: Vec<AgentResponse>) -> Self {
+            Self {
+                responses: std::sync::Mutex::new(responses.into()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LLMProvider for ScriptedProvider {
+        async fn chat(&self, _request: AgentRequest) -> Result<AgentResponse, AgentError> {
+            self.responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| AgentError::ApiError("script exhausted".to_string()))
+        }
+    }
+
+    fn tool_call(name: &str, arguments: serde_json::Value) -> AgentResponse {
+        AgentResponse {
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: name.to_string(),
+                arguments,
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn answer(result: &str) -> AgentResponse {
+        AgentResponse {
+            result: result.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Tool that reports progress before answering
+    struct SearchTool;
+
+    #[async_trait]
+    impl Tool for SearchTool {
+        fn name(&self) -> &str {
+            "search"
+        }
+
+        fn description(&self) -> &str {
+            "Search the web"
+        }
+
+        async fn call(
+            &self,
+            arguments: serde_json::Value,
+            progress: &ToolProgressSender,
+        ) -> Result<String, AgentError> {
+            progress.report("fetching results");
+            Ok(format!("results for {}", arguments["query"]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_stream_reports_tool_progress() {
+        let provider = ScriptedProvider::new(vec![
+            tool_call("search", serde_json::json!({"query": "rust"})),
+            answer("Rust is a language"),
+        ]);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(SearchTool));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let response = agent
+            .execute_stream("What is Rust?".to_string(), tx)
+            .await
+            .unwrap();
+        assert_eq!(response.result, "Rust is a language");
+
+        let mut kinds = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            kinds.push(match event {
+                StreamEvent::Thought(thought) => thought.thought_type,
+                StreamEvent::ToolProgress { tool, message } => format!("{}: {}", tool, message),
+                other => other.name().to_string(),
+            });
+        }
+        // `accepted` comes first, and only once
+        assert_eq!(
+            kinds,
+            [
+                "accepted",
+                "action",
+                "search: fetching results",
+                "observation",
+                "delta",
+                "done"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inflight_runs_are_listed_until_done() {
+        let agent = Arc::new(ReActAgent::new(
+            Box::new(StallingProvider),
+            Box::new(MemoryVectorStore::new()),
+        ));
+        let options = RunOptions {
+            model: Some("gpt-4o".to_string()),
+            ..Default::default()
+        };
+        let running = agent.clone();
+        let run = tokio::spawn(async move {
+            let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+            running
+                .execute_stream_with("Hello".to_string(), options, tx)
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let inflight = agent.inflight();
+        assert_eq!(inflight.len(), 1);
+        assert_eq!(inflight[0].step, 1);
+        assert_eq!(inflight[0].model.as_deref(), Some("gpt-4o"));
+
+        run.abort();
+        let _ = run.await;
+        assert!(agent.inflight().is_empty());
+    }
+
+    /// Tool that hands its task to a sub-agent
+    struct DelegateTool {
+        agent: ReActAgent,
+    }
+
+    #[async_trait]
+    impl Tool for DelegateTool {
+        fn name(&self) -> &str {
+            "delegate"
+        }
+
+        fn description(&self) -> &str {
+            "Ask a research sub-agent"
+        }
+
+        async fn call(
+            &self,
+            arguments: serde_json::Value,
+            progress: &ToolProgressSender,
+        ) -> Result<String, AgentError> {
+            let task = arguments["task"].as_str().unwrap_or_default().to_string();
+            let response = self.agent.execute(task).await?;
+            progress.attach_trace(response.trace.unwrap_or_default());
+            Ok(response.result)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trace_tree_nests_sub_agent_runs() {
+        let sub_agent = ReActAgent::new(
+            Box::new(ScriptedProvider::new(vec![
+                tool_call("search", serde_json::json!({"query": "rust"})),
+                answer("Rust is a language"),
+            ])),
+            Box::new(MemoryVectorStore::new()),
+        )
+        .with_tool(Box::new(SearchTool))
+        .with_trace_tree();
+        let agent = ReActAgent::new(
+            Box::new(ScriptedProvider::new(vec![
+                tool_call("delegate", serde_json::json!({"task": "What is Rust?"})),
+                answer("It is a language"),
+            ])),
+            Box::new(MemoryVectorStore::new()),
+        )
+        .with_tool(Box::new(DelegateTool { agent: sub_agent }))
+        .with_trace_tree();
+
+        let response = agent.execute("Explain Rust".to_string()).await.unwrap();
+        let trace = response.trace.unwrap();
+        let TraceNode::ToolCall {
+            name,
+            observation,
+            children,
+            ..
+        } = &trace[0]
+        else {
+            panic!("expected a tool call, got {:?}", trace[0]);
+        };
+        assert_eq!(name, "delegate");
+        assert_eq!(observation, "Rust is a language");
+        assert!(matches!(
+            &children[..],
+            [TraceNode::ToolCall { name, children, .. }] if name == "search" && children.is_empty()
+        ));
+        assert_eq!(response.thoughts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_plan_only_returns_tool_calls_without_running_them() {
+        let provider = ScriptedProvider::new(vec![tool_call(
+            "search",
+            serde_json::json!({"query": "rust"}),
+        )]);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(SearchTool));
+        let options = RunOptions {
+            plan_only: true,
+            ..Default::default()
+        };
+
+        let response = agent
+            .execute_with("What is Rust?".to_string(), options)
+            .await
+            .unwrap();
+        assert!(response.plan_only);
+        assert_eq!(response.tool_calls[0].name, "search");
+        assert_eq!(response.thoughts[0].content, r#"search({"query":"rust"})"#);
+        assert!(agent.tool_stats().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_json_patches_follow_deltas() {
+        let provider = MockProvider::new().with_response(answer(r#"{"city": "Paris"}"#));
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_json_patches();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        agent
+            .execute_stream("Where?".to_string(), tx)
+            .await
+            .unwrap();
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        assert_eq!(events[1].name(), "delta");
+        assert!(matches!(
+            &events[2],
+            StreamEvent::Patch { patch } if *patch == serde_json::json!({"city": "Paris"})
+        ));
+    }
+
+    /// Provider that streams one delta and then stalls
+    struct StallingProvider;
+
+    #[async_trait]
+    impl LLMProvider for StallingProvider {
+        async fn chat(&self, _request: AgentRequest) -> Result<AgentResponse, AgentError> {
+            unreachable!("only streaming is tested")
+        }
+
+        async fn chat_stream(
+            &self,
+            _request: AgentRequest,
+            deltas: UnboundedSender<String>,
+        ) -> Result<AgentResponse, AgentError> {
+            let _ = deltas.send("Rust is".to_string());
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inter_token_timeout_cancels_stalled_stream() {
+        let agent = ReActAgent::new(
+            Box::new(StallingProvider),
+            Box::new(MemoryVectorStore::new()),
+        )
+        .with_inter_token_timeout(Duration::from_millis(50));
+
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let error = agent
+            .execute_stream("What is Rust?".to_string(), tx)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, AgentError::Timeout { ref partial, .. } if partial == "Rust is"),
+            "{:?}",
+            error
+        );
+    }
+
+    /// Provider that needs a handshake before it can chat
+    #[derive(Default)]
+    struct HandshakeProvider {
+        session: std::sync::OnceLock<String>,
+    }
+
+    #[async_trait]
+    impl LLMProvider for HandshakeProvider {
+        async fn chat(&self, _request: AgentRequest) -> Result<AgentResponse, AgentError> {
+            match self.session.get() {
+                Some(session) => Ok(answer(session)),
+                None => Err(AgentError::ApiError("no session".to_string())),
+            }
+        }
+
+        async fn init(&self) -> Result<(), AgentError> {
+            let _ = self.session.set("session-1".to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_init_runs_provider_handshake() {
+        let agent = ReActAgent::new(
+            Box::new(HandshakeProvider::default()),
+            Box::new(MemoryVectorStore::new()),
+        );
+        assert!(agent.execute("Hi".to_string()).await.is_err());
+
+        agent.init().await.unwrap();
+        let response = agent.execute("Hi".to_string()).await.unwrap();
+        assert_eq!(response.result, "session-1");
+    }
+
+    /// Provider that takes exactly `elapsed` on a mock clock
+    struct TimedProvider {
+        clock: Arc<MockClock>,
+        elapsed: Duration,
+    }
+
+    #[async_trait]
+    impl LLMProvider for TimedProvider {
+        async fn chat(&self, _request: AgentRequest) -> Result<AgentResponse, AgentError> {
+            self.clock.advance(self.elapsed);
+            Ok(answer("done"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock_makes_durations_exact() {
+        let clock = Arc::new(MockClock::new());
+        let provider = TimedProvider {
+            clock: clock.clone(),
+            elapsed: Duration::from_millis(1500),
+        };
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_clock(clock);
+
+        let response = agent.execute("Wait".to_string()).await.unwrap();
+        assert_eq!(response.duration_ms, 1500);
+
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let response = agent.execute_stream("Wait".to_string(), tx).await.unwrap();
+        assert_eq!(response.duration_ms, 1500);
+        assert_eq!(response.ttft_ms, Some(1500));
+    }
+
+    #[tokio::test]
+    async fn test_execute_runs_tools_without_stream() {
+        let provider = ScriptedProvider::new(vec![
+            tool_call("search", serde_json::json!({"query": "rust"})),
+            tool_call("missing", serde_json::json!({})),
+            answer("done"),
+        ]);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(SearchTool));
+
+        let response = agent.execute("What is Rust?".to_string()).await.unwrap();
+        assert_eq!(response.result, "done");
+        let observations: Vec<_> = response
+            .thoughts
+            .iter()
+            .filter(|t| t.thought_type == "observation")
+            .map(|t| t.content.as_str())
+            .collect();
+        assert_eq!(
+            observations,
+            ["results for \"rust\"", "Unknown tool: missing"]
+        );
+        assert_eq!(response.warnings.len(), 1);
+        assert!(response.warnings[0].contains("tool missing failed"));
+    }
+
+    #[tokio::test]
+    async fn test_max_thoughts_keeps_most_recent() {
+        let provider = ScriptedProvider::new(vec![
+            tool_call("search", serde_json::json!({"query": "rust"})),
+            answer("done"),
+        ]);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(SearchTool))
+            .with_max_thoughts(1);
+
+        let response = agent.execute("What is Rust?".to_string()).await.unwrap();
+        assert!(response.thoughts_truncated);
+        assert_eq!(response.thoughts.len(), 1);
+        assert_eq!(
+            response.warnings,
+            ["1 older thoughts were dropped from the response"]
+        );
+        assert_eq!(response.thoughts[0].thought_type, "observation");
+
+        let agent = ReActAgent::new(
+            Box::new(MockProvider::new()),
+            Box::new(MemoryVectorStore::new()),
+        )
+        .with_max_thoughts(5);
+        let response = agent.execute("Hello".to_string()).await.unwrap();
+        assert!(!response.thoughts_truncated);
+        assert_eq!(response.thoughts.len(), 1);
+        assert!(response.warnings.is_empty());
+    }
+
+    /// Records the `content` field of every event
+    struct ContentRecorder(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for ContentRecorder {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _context: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            struct Content<'a>(&'a mut Vec<String>);
+
+            impl tracing::field::Visit for Content<'_> {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    if field.name() == "content" {
+                        self.0.push(format!("{:?}", value));
+                    }
+                }
+            }
+
+            event.record(&mut Content(&mut self.0.lock().unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropped_thoughts_are_logged() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let contents = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(ContentRecorder(contents.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let provider = ScriptedProvider::new(vec![
+            tool_call("search", serde_json::json!({"query": "rust"})),
+            answer("done"),
+        ]);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(SearchTool))
+            .with_max_thoughts(1);
+        let response = agent.execute("What is Rust?".to_string()).await.unwrap();
+
+        let contents = contents.lock().unwrap();
+        assert_eq!(contents.len(), 1);
+        assert_ne!(contents[0], response.thoughts[0].content);
+        assert!(contents[0].contains("search"));
+    }
+
+    /// Tool returning a document too long to keep in context
+    struct FetchTool;
+
+    #[async_trait]
+    impl Tool for FetchTool {
+        fn name(&self) -> &str {
+            "fetch"
+        }
+
+        fn description(&self) -> &str {
+            "Fetch a web page"
+        }
+
+        async fn call(
+            &self,
+            _arguments: serde_json::Value,
+            _progress: &ToolProgressSender,
+        ) -> Result<String, AgentError> {
+            Ok(format!("{} The answer is 42.", "filler words ".repeat(200)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ingested_tool_output_goes_to_vector_store() {
+        let agent = |ingest: bool| {
+            let provider = ScriptedProvider::new(vec![
+                tool_call("fetch", serde_json::json!({})),
+                answer("42"),
+            ]);
+            let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+                .with_tool(Box::new(FetchTool));
+            if ingest {
+                agent.with_ingested_tool("fetch")
+            } else {
+                agent
+            }
         };
-        
-        let response = provider.chat(request).await.unwrap();
-        assert!(response.result.contains("Hello"));
+
+        let inline = agent(false);
+        let response = inline.execute("Fetch it".to_string()).await.unwrap();
+        assert!(response.thoughts[1].content.ends_with("The answer is 42."));
+        assert!(inline
+            .vector_store()
+            .search("answer".to_string(), 5)
+            .unwrap()
+            .is_empty());
+
+        let ingested = agent(true);
+        let response = ingested.execute("Fetch it".to_string()).await.unwrap();
+        let observation = &response.thoughts[1].content;
+        assert!(observation.starts_with("Stored 2618 characters of output in 3 chunks"));
+        assert!(!observation.contains("42"));
+
+        let hits = ingested
+            .vector_store()
+            .search("answer".to_string(), 1)
+            .unwrap();
+        assert!(hits[0].0.ends_with("The answer is 42."));
+    }
+
+    /// Sink that keeps events in memory
+    #[derive(Default)]
+    struct CollectingSink {
+        events: std::sync::Mutex<Vec<AgentEvent>>,
+    }
+
+    impl EventSink for CollectingSink {
+        fn emit(&self, event: AgentEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_sink_receives_run_events() {
+        let sink = Arc::new(CollectingSink::default());
+        let provider = ScriptedProvider::new(vec![
+            tool_call("search", serde_json::json!({"query": "rust"})),
+            answer("Rust is a language"),
+        ]);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(SearchTool))
+            .with_event_sink(sink.clone());
+
+        agent.execute("What is Rust?".to_string()).await.unwrap();
+        agent.execute("Again".to_string()).await.unwrap_err();
+
+        let events = sink.events.lock().unwrap();
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|e| match &e.kind {
+                AgentEventKind::RequestReceived { .. } => "request",
+                AgentEventKind::Step { .. } => "step",
+                AgentEventKind::ToolCall { .. } => "tool_call",
+                AgentEventKind::Response { .. } => "response",
+                AgentEventKind::Paused { .. } => "paused",
+                AgentEventKind::Failed { .. } => "failed",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "request",
+                "step",
+                "tool_call",
+                "step",
+                "response",
+                "request",
+                "step",
+                "failed"
+            ]
+        );
+        assert!(events[..5].iter().all(|e| e.run_id == events[0].run_id));
+        assert_ne!(events[5].run_id, events[0].run_id);
+    }
+
+    /// Tool that always fails
+    struct BrokenTool;
+
+    #[async_trait]
+    impl Tool for BrokenTool {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn description(&self) -> &str {
+            "Always fails"
+        }
+
+        async fn call(
+            &self,
+            _arguments: serde_json::Value,
+            _progress: &ToolProgressSender,
+        ) -> Result<String, AgentError> {
+            Err(AgentError::ApiError("unavailable".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_stats_count_calls_and_failures() {
+        let provider = ScriptedProvider::new(vec![
+            tool_call("search", serde_json::json!({"query": "rust"})),
+            tool_call("broken", serde_json::json!({})),
+            answer("done"),
+        ]);
+        let metrics = Arc::new(Metrics::new());
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(SearchTool))
+            .with_tool(Box::new(BrokenTool))
+            .with_metrics(metrics.clone());
+        agent.execute("Look it up".to_string()).await.unwrap();
+
+        let stats = agent.tool_stats();
+        assert_eq!(stats["search"].calls, 1);
+        assert_eq!(stats["search"].failures, 0);
+        assert_eq!(stats["broken"].failures, 1);
+        assert!(metrics
+            .render()
+            .contains("agent_tool_calls_total{tool=\"broken\",outcome=\"failure\"} 1"));
+    }
+
+    /// Tool that takes a password
+    struct LoginTool;
+
+    #[async_trait]
+    impl Tool for LoginTool {
+        fn name(&self) -> &str {
+            "login"
+        }
+
+        fn description(&self) -> &str {
+            "Log in to the database"
+        }
+
+        fn sensitive_arguments(&self) -> &[&str] {
+            &["password"]
+        }
+
+        async fn call(
+            &self,
+            arguments: serde_json::Value,
+            _progress: &ToolProgressSender,
+        ) -> Result<String, AgentError> {
+            assert_eq!(arguments["password"], "hunter2");
+            Ok("logged in".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sensitive_tool_arguments_are_redacted_in_events() {
+        let sink = Arc::new(CollectingSink::default());
+        let provider = ScriptedProvider::new(vec![
+            tool_call(
+                "login",
+                serde_json::json!({"user": "admin", "password": "hunter2"}),
+            ),
+            answer("Logged in"),
+        ]);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(LoginTool))
+            .with_event_sink(sink.clone());
+        agent.execute("Log in".to_string()).await.unwrap();
+
+        let events = sink.events.lock().unwrap();
+        let arguments = events
+            .iter()
+            .find_map(|e| match &e.kind {
+                AgentEventKind::ToolCall { arguments, .. } => Some(arguments.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            arguments,
+            serde_json::json!({"user": "admin", "password": REDACTED})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sensitive_tool_arguments_are_redacted_in_thoughts() {
+        let login = || {
+            tool_call(
+                "login",
+                serde_json::json!({"user": "admin", "password": "hunter2"}),
+            )
+        };
+        let agent = |responses| {
+            ReActAgent::new(
+                Box::new(ScriptedProvider::new(responses)),
+                Box::new(MemoryVectorStore::new()),
+            )
+            .with_tool(Box::new(LoginTool))
+        };
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let response = agent(vec![login(), answer("Logged in")])
+            .execute_stream("Log in".to_string(), tx)
+            .await
+            .unwrap();
+        let plan = RunOptions {
+            plan_only: true,
+            ..Default::default()
+        };
+        let planned = agent(vec![login()])
+            .execute_with("Log in".to_string(), plan)
+            .await
+            .unwrap();
+
+        // Nor do the streamed thoughts
+        while let Ok(event) = rx.try_recv() {
+            assert!(!serde_json::to_string(&event).unwrap().contains("hunter2"));
+        }
+        for thoughts in [&response.thoughts, &planned.thoughts] {
+            let action = thoughts
+                .iter()
+                .find(|t| t.thought_type == "action")
+                .unwrap();
+            assert!(action.content.contains(REDACTED), "{}", action.content);
+            assert!(thoughts.iter().all(|t| !t.content.contains("hunter2")));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_round_trip() {
+        let script = || {
+            vec![
+                tool_call("search", serde_json::json!({"query": "rust"})),
+                tool_call("search", serde_json::json!({"query": "ownership"})),
+                answer("Rust is a language"),
+            ]
+        };
+        let agent = |responses| {
+            ReActAgent::new(
+                Box::new(ScriptedProvider::new(responses)),
+                Box::new(MemoryVectorStore::new()),
+            )
+            .with_tool(Box::new(SearchTool))
+        };
+        let pause = RunOptions {
+            pause_after: Some(1),
+            ..Default::default()
+        };
+
+        let uninterrupted = agent(script())
+            .execute("What is Rust?".to_string())
+            .await
+            .unwrap();
+
+        let mut remaining = script();
+        let first = agent(remaining.drain(..1).collect());
+        let RunOutcome::Paused(state) = first
+            .resume(RunState::new("What is Rust?"), pause.clone())
+            .await
+            .unwrap()
+        else {
+            panic!("expected the run to pause");
+        };
+        assert_eq!(state.step, 1);
+        // The tool call and its result are kept as provider-neutral turns
+        assert_eq!(state.turns.len(), 2);
+        assert_eq!(state.turns[0].tool_calls[0].id, "call_1");
+        assert_eq!(
+            state.turns[1].tool_result.as_ref().unwrap().call_id,
+            "call_1"
+        );
+        assert!(first
+            .execute_with("What is Rust?".to_string(), pause)
+            .await
+            .is_err());
+
+        // Hand the checkpoint to a fresh agent, as another worker would
+        let state: RunState =
+            serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        let RunOutcome::Finished(resumed) = agent(remaining)
+            .resume(state, RunOptions::default())
+            .await
+            .unwrap()
+        else {
+            panic!("expected the run to finish");
+        };
+        assert_eq!(resumed.result, uninterrupted.result);
+        assert_eq!(resumed.thoughts, uninterrupted.thoughts);
+    }
+
+    #[tokio::test]
+    async fn test_retrieval_reports_used_and_dropped_chunks() {
+        let store = MemoryVectorStore::new();
+        for doc in [
+            "rust ownership rules",
+            "rust borrowing and lifetimes in rust explained at length",
+            "rust traits",
+        ] {
+            store.add(doc.to_string(), serde_json::json!({})).unwrap();
+        }
+        let agent =
+            ReActAgent::new(Box::new(MockProvider::new()), Box::new(store)).with_retrieval(3, 40);
+
+        let response = agent.execute("rust".to_string()).await.unwrap();
+        assert_eq!(response.retrieved_chunks, 3);
+        assert_eq!(response.used_chunks, 2);
+        assert_eq!(
+            response.dropped_chunks,
+            ["rust borrowing and lifetimes in rust explained at length"]
+        );
+        assert!(response
+            .result
+            .starts_with("Mock response for: Context:\n- "));
+        assert!(response.result.ends_with("\n\nrust"));
+    }
+
+    #[tokio::test]
+    async fn test_max_prompt_bytes_rejects_before_calling_provider() {
+        let store = MemoryVectorStore::new();
+        store
+            .add("rust ".repeat(100), serde_json::json!({}))
+            .unwrap();
+        let agent = ReActAgent::new(Box::new(MockProvider::new()), Box::new(store))
+            .with_retrieval(1, 1000)
+            .with_max_prompt_bytes(200);
+
+        assert!(matches!(
+            agent.execute("rust".to_string()).await,
+            Err(AgentError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_execute_json_reprompts_until_valid() {
+        let agent = |retries: usize| {
+            let provider = ScriptedProvider::new(vec![
+                answer("The capital is Paris."),
+                answer("{\"city\": \"Paris\""),
+                answer("```json\n{\"city\": \"Paris\"}\n```"),
+            ]);
+            ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+                .with_json_retries(retries)
+        };
+
+        let value = agent(2)
+            .execute_json("Capital of France?".to_string(), RunOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(value, serde_json::json!({"city": "Paris"}));
+
+        let error = agent(1)
+            .execute_json("Capital of France?".to_string(), RunOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &error,
+            AgentError::ParseError(message)
+                if message.starts_with("answer is not valid JSON after 1 retries")
+                    && message.ends_with("last output: {\"city\": \"Paris\"")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_execute_json_retries_share_the_run_budget() {
+        let reply = |content: &str| {
+            serde_json::json!({
+                "choices": [{"message": {"content": content}}],
+                "usage": {"prompt_tokens": 50, "completion_tokens": 10},
+            })
+        };
+        let transport = Arc::new(
+            MockTransport::new()
+                .with_json(200, reply("Paris"))
+                .with_json(200, reply("{\"city\": \"Paris\"}")),
+        );
+        let provider =
+            OpenAIProvider::new("test-key".to_string()).with_transport(transport.clone());
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_json_retries(1)
+            .with_token_budget(100);
+
+        let error = agent
+            .execute_json("Capital of France?".to_string(), RunOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            AgentError::TokenBudgetExceeded { used: 120, .. }
+        ));
+        // The retry continued the conversation
+        let retry: serde_json::Value =
+            serde_json::from_slice(&transport.requests()[1].body).unwrap();
+        let messages = retry["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["content"], "Capital of France?");
+        assert_eq!(
+            messages[1],
+            serde_json::json!({"role": "assistant", "content": "Paris"})
+        );
+        assert_eq!(messages[2]["role"], "user");
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_aborts_runaway_run() {
+        let step = |usage: u64| AgentResponse {
+            usage: Some(Usage {
+                input_tokens: usage,
+                output_tokens: 10,
+            }),
+            ..tool_call("search", serde_json::json!({"query": "rust"}))
+        };
+        let agent = |budget: Option<u64>| {
+            let agent = ReActAgent::new(
+                Box::new(ScriptedProvider::new(vec![
+                    step(40),
+                    step(40),
+                    step(40),
+                    answer("done"),
+                ])),
+                Box::new(MemoryVectorStore::new()),
+            )
+            .with_tool(Box::new(SearchTool));
+            match budget {
+                Some(budget) => agent.with_token_budget(budget),
+                None => agent,
+            }
+        };
+
+        let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let error = agent(Some(100))
+            .execute_stream("Research rust".to_string(), events)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Token budget exceeded: 150 of 100 tokens used"
+        );
+        // The trace up to the third step reached the stream before the error;
+        // its tool call was never made
+        let mut actions = 0;
+        while let Ok(event) = received.try_recv() {
+            if matches!(&event, StreamEvent::Thought(t) if t.thought_type == "action") {
+                actions += 1;
+            }
+        }
+        assert_eq!(actions, 2);
+
+        // Without a stream the error carries the partial trace
+        let error = agent(Some(100))
+            .execute("Research rust".to_string())
+            .await
+            .unwrap_err();
+        let AgentError::TokenBudgetExceeded {
+            used,
+            budget,
+            steps,
+            thoughts,
+        } = error
+        else {
+            panic!("expected TokenBudgetExceeded, got {:?}", error);
+        };
+        assert_eq!((used, budget, steps), (150, 100, 3));
+        let actions = thoughts.iter().filter(|t| t.thought_type == "action");
+        assert_eq!(actions.count(), 2);
+
+        let response = agent(None)
+            .execute("Research rust".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.result, "done");
+        assert_eq!(response.usage.unwrap().total(), 150);
+    }
+
+    #[tokio::test]
+    async fn test_relevance_gate_skips_retrieval() {
+        let store = MemoryVectorStore::new();
+        store
+            .add("rust ownership rules".to_string(), serde_json::json!({}))
+            .unwrap();
+        let agent = ReActAgent::new(Box::new(MockProvider::new()), Box::new(store))
+            .with_retrieval(3, 100)
+            .with_retrieval_gate(Box::new(MinWordsGate::new(3)));
+
+        let response = agent.execute("hi".to_string()).await.unwrap();
+        assert!(response.retrieval_skipped);
+        assert_eq!(response.retrieved_chunks, 0);
+        assert_eq!(response.result, "Mock response for: hi");
+        assert_eq!(response.provider, "mock");
+
+        let response = agent
+            .execute("explain rust ownership".to_string())
+            .await
+            .unwrap();
+        assert!(!response.retrieval_skipped);
+        assert_eq!(response.retrieved_chunks, 1);
+    }
+
+    /// Tool that sleeps for `ms` and records when it finished
+    struct SleepTool {
+        finished: Arc<std::sync::Mutex<Vec<u64>>>,
+    }
+
+    #[async_trait]
+    impl Tool for SleepTool {
+        fn name(&self) -> &str {
+            "sleep"
+        }
+
+        fn description(&self) -> &str {
+            "Sleep for a while"
+        }
+
+        async fn call(
+            &self,
+            arguments: serde_json::Value,
+            _progress: &ToolProgressSender,
+        ) -> Result<String, AgentError> {
+            let ms = arguments["ms"].as_u64().unwrap_or_default();
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            self.finished.lock().unwrap().push(ms);
+            Ok(format!("slept {} ms", ms))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sequential_tools_run_in_emitted_order() {
+        let finish_order = |sequential: bool| async move {
+            let mut calls = tool_call("sleep", serde_json::json!({"ms": 60}));
+            calls.tool_calls.push(ToolCall {
+                id: "call_2".to_string(),
+                name: "sleep".to_string(),
+                arguments: serde_json::json!({"ms": 5}),
+            });
+            let finished = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let agent = ReActAgent::new(
+                Box::new(ScriptedProvider::new(vec![calls, answer("done")])),
+                Box::new(MemoryVectorStore::new()),
+            )
+            .with_tool(Box::new(SleepTool {
+                finished: finished.clone(),
+            }))
+            .with_sequential_tools(sequential);
+
+            agent.execute("Sleep".to_string()).await.unwrap();
+            let order = finished.lock().unwrap().clone();
+            order
+        };
+
+        assert_eq!(finish_order(false).await, [5, 60]);
+        assert_eq!(finish_order(true).await, [60, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_approval_required_tools_wait_for_approver() {
+        let agent = || {
+            let provider = ScriptedProvider::new(vec![
+                tool_call("search", serde_json::json!({"query": "rust"})),
+                answer("Rust is a language"),
+            ]);
+            ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+                .with_tool(Box::new(SearchTool))
+                .with_approval_required("search")
+        };
+        let observation = |response: &AgentResponse| {
+            response
+                .thoughts
+                .iter()
+                .find(|t| t.thought_type == "observation")
+                .map(|t| t.content.clone())
+        };
+
+        // Non-interactive runs deny
+        let response = agent().execute("What is Rust?".to_string()).await.unwrap();
+        assert_eq!(observation(&response).as_deref(), Some(TOOL_DENIED));
+
+        let approvals = Arc::new(ApprovalQueue::new());
+        let options = RunOptions {
+            approver: Some(approvals.clone()),
+            ..Default::default()
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let agent = agent();
+        let run = agent.execute_stream_with("What is Rust?".to_string(), options, tx);
+        let approve = async {
+            while let Some(event) = rx.recv().await {
+                if let StreamEvent::ApprovalRequested { call } = event {
+                    assert!(approvals.resolve(&call.id, true));
+                }
+            }
+        };
+        let (response, ()) = tokio::join!(run, approve);
+        assert_eq!(
+            observation(&response.unwrap()).as_deref(),
+            Some("results for \"rust\"")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prompt_linters_warn_and_abort() {
+        let provider = ScriptedProvider::new(vec![
+            tool_call("search", serde_json::json!({"query": "rust"})),
+            answer("Rust is a language"),
+        ]);
+        let agent = ReActAgent::new(Box::new(provider), Box::new(MemoryVectorStore::new()))
+            .with_tool(Box::new(SearchTool))
+            .with_prompt_linter(Box::new(EmptySectionLinter));
+        let response = agent
+            .execute("Examples:\n\nWhat is Rust?".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.warnings, ["prompt section \"Examples:\" is empty"]);
+
+        let agent = ReActAgent::new(
+            Box::new(MockProvider::new()),
+            Box::new(MemoryVectorStore::new()),
+        )
+        .with_prompt_linter(Box::new(MaxLengthLinter::new(5)));
+        assert!(matches!(
+            agent.execute("What is Rust?".to_string()).await,
+            Err(AgentError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_final_answer_extraction() {
+        let message = "Thought: I know this.\nFinal Answer: Rust is a language\n";
+        let agent = |message: &str| {
+            ReActAgent::new(
+                Box::new(ScriptedProvider::new(vec![answer(message)])),
+                Box::new(MemoryVectorStore::new()),
+            )
+        };
+
+        let response = agent(message)
+            .execute("What is Rust?".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.result, "Rust is a language");
+        assert_eq!(extract_final_answer("  plain answer "), "plain answer");
+
+        let response = agent("<answer>42</answer>")
+            .with_answer_extractor(|m| {
+                m.trim_start_matches("<answer>")
+                    .trim_end_matches("</answer>")
+                    .to_string()
+            })
+            .execute("What is the answer?".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.result, "42");
+    }
+
+    #[tokio::test]
+    async fn test_max_result_chars_truncates_at_char_boundary() {
+        let agent = || {
+            ReActAgent::new(
+                Box::new(ScriptedProvider::new(vec![answer("Größe matters")])),
+                Box::new(MemoryVectorStore::new()),
+            )
+        };
+
+        let response = agent()
+            .with_max_result_chars(5)
+            .execute("Size?".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.result, "Größ…");
+        assert!(response.truncated);
+
+        let response = agent()
+            .with_max_result_chars(100)
+            .execute("Size?".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.result, "Größe matters");
+        assert!(!response.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_returns_partial_when_enabled() {
+        let agent = |partial: bool| {
+            let mut draft = tool_call("search", serde_json::json!({"query": "rust"}));
+            draft.result = "Rust is probably a language".to_string();
+            let provider = ScriptedProvider::new(vec![draft, answer("Rust is a language")]);
+            let agent = ReActAgent::new(
+                Box::new(
+                    ChaosProvider::new(Box::new(provider)).with_latency(Duration::from_millis(100)),
+                ),
+                Box::new(MemoryVectorStore::new()),
+            )
+            .with_tool(Box::new(SearchTool))
+            .with_timeout(Duration::from_millis(150));
+            if partial {
+                agent.with_partial_on_timeout()
+            } else {
+                agent
+            }
+        };
+
+        let error = agent(false)
+            .execute("What is Rust?".to_string())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out after 150 ms"));
+
+        let response = agent(true)
+            .execute("What is Rust?".to_string())
+            .await
+            .unwrap();
+        assert!(response.timed_out);
+        assert_eq!(response.result, "Rust is probably a language");
+        assert_eq!(response.thoughts.len(), 2);
+        assert_eq!(response.warnings.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ttft_only_measured_when_streaming() {
+        let metrics = Arc::new(Metrics::new());
+        let agent = ReActAgent::new(
+            Box::new(
+                ChaosProvider::new(Box::new(MockProvider::new()))
+                    .with_latency(Duration::from_millis(20)),
+            ),
+            Box::new(MemoryVectorStore::new()),
+        )
+        .with_metrics(metrics.clone());
+
+        let response = agent.execute("Hello".to_string()).await.unwrap();
+        assert_eq!(response.ttft_ms, None);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let response = agent.execute_stream("Hello".to_string(), tx).await.unwrap();
+        assert!(response.ttft_ms.unwrap() >= 20);
+        assert_eq!(metrics.ttft_ms.count(), 1);
+        assert!(matches!(
+            rx.recv().await,
+            Some(StreamEvent::Accepted { .. })
+        ));
+        assert!(matches!(rx.recv().await, Some(StreamEvent::Delta { .. })));
     }
 
     #[test]
@@ -214,4 +4168,349 @@ mod tests {
         let results = store.search("test".to_string(), 1).unwrap();
         assert_eq!(results.len(), 1);
     }
+
+    #[test]
+    fn test_search_with_embeddings() {
+        let store = MemoryVectorStore::with_embedder(Box::new(HashEmbedder::new(32)));
+        store
+            .add("rust ownership".to_string(), serde_json::json!({}))
+            .unwrap();
+        store
+            .add("python typing".to_string(), serde_json::json!({}))
+            .unwrap();
+
+        let results = store.search_with_embeddings("rust".to_string(), 2).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, "rust ownership");
+        assert!(results[0].1 > results[1].1);
+        assert_eq!(
+            results[0].2,
+            HashEmbedder::new(32).embed("rust ownership").unwrap()
+        );
+    }
+
+    /// Embedder that cannot tell documents apart, like a model that has
+    /// never seen the rare terms being searched for
+    struct BlindEmbedder;
+
+    impl Embedder for BlindEmbedder {
+        fn dimensions(&self) -> usize {
+            1
+        }
+
+        fn embed(&self, _text: &str) -> Result<Vec<f32>, AgentError> {
+            Ok(vec![1.0])
+        }
+    }
+
+    #[test]
+    fn test_search_pages_cover_ties_without_duplicates() {
+        // Every document scores the same, so only the tie-break orders them
+        let store = MemoryVectorStore::with_embedder(Box::new(BlindEmbedder));
+        for i in 0..10 {
+            store
+                .add(format!("doc {}", i), serde_json::json!({}))
+                .unwrap();
+        }
+
+        let mut seen = Vec::new();
+        for offset in (0..12).step_by(3) {
+            let page = store.search_page("query".to_string(), offset, 3).unwrap();
+            assert!(page.len() <= 3);
+            seen.extend(page.into_iter().map(|(text, _)| text));
+        }
+        let expected: Vec<String> = (0..10).map(|i| format!("doc {}", i)).collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn test_document_embedder_per_metadata_type() {
+        assert!(MemoryVectorStore::new()
+            .with_document_embedder("type", "code", Box::new(HashEmbedder::new(8)))
+            .is_err());
+
+        let store = MemoryVectorStore::with_embedder(Box::new(BlindEmbedder))
+            .with_document_embedder("type", "code", Box::new(HashEmbedder::new(1)))
+            .unwrap();
+        store
+            .add(
+                "fn main() {}".to_string(),
+                serde_json::json!({"type": "code"}),
+            )
+            .unwrap();
+        store
+            .add("prose".to_string(), serde_json::json!({"type": "prose"}))
+            .unwrap();
+
+        let documents = store.documents.read().unwrap();
+        assert_eq!(
+            documents[0].embedding,
+            HashEmbedder::new(1).embed("fn main() {}").unwrap()
+        );
+        assert_eq!(documents[1].embedding, [1.0]);
+    }
+
+    #[test]
+    fn test_normalized_embeddings_match_cosine() {
+        let docs = ["Rust is fast", "Python is dynamic", "Rust has ownership"];
+        let plain = MemoryVectorStore::new();
+        let normalized = MemoryVectorStore::new().with_normalized_embeddings();
+        for doc in docs {
+            plain.add(doc.to_string(), serde_json::json!({})).unwrap();
+            normalized
+                .add(doc.to_string(), serde_json::json!({}))
+                .unwrap();
+        }
+
+        let query = "rust ownership".to_string();
+        let expected = plain.search_with_embeddings(query.clone(), 3).unwrap();
+        let actual = normalized.search_with_embeddings(query, 3).unwrap();
+        for ((text, score, _), (expected_text, expected_score, _)) in actual.iter().zip(&expected) {
+            assert_eq!(text, expected_text);
+            assert!((score - expected_score).abs() < 1e-5);
+        }
+        let norm: f32 = actual[0].2.iter().map(|x| x * x).sum();
+        assert!((norm - 1.0).abs() < 1e-5);
+    }
+
+    /// `HashEmbedder` vectors scaled to length `norm`
+    struct ScaledEmbedder {
+        norm: f32,
+    }
+
+    impl Embedder for ScaledEmbedder {
+        fn dimensions(&self) -> usize {
+            HashEmbedder::default().dimensions()
+        }
+
+        fn embed(&self, text: &str) -> Result<Vec<f32>, AgentError> {
+            let mut vector = HashEmbedder::default().embed(text)?;
+            l2_normalize(&mut vector);
+            Ok(vector.into_iter().map(|x| x * self.norm).collect())
+        }
+
+        fn normalizes(&self) -> bool {
+            self.norm == 1.0
+        }
+    }
+
+    #[test]
+    fn test_mixed_normalized_and_raw_vectors_rank_consistently() {
+        let docs = [
+            ("Rust is fast", "unit"),
+            ("Python is dynamic", "raw"),
+            ("Rust has ownership", "raw"),
+            ("Ownership rules in Rust", "unit"),
+        ];
+        // Pre-normalized queries switch the store to dot-product scoring;
+        // half of the documents come from an embedder that does not normalize
+        let mixed = MemoryVectorStore::with_embedder(Box::new(ScaledEmbedder { norm: 1.0 }))
+            .with_document_embedder("source", "raw", Box::new(ScaledEmbedder { norm: 7.5 }))
+            .unwrap();
+        let cosine = MemoryVectorStore::new();
+        for (doc, source) in docs {
+            let metadata = serde_json::json!({ "source": source });
+            mixed.add(doc.to_string(), metadata.clone()).unwrap();
+            cosine.add(doc.to_string(), metadata).unwrap();
+        }
+
+        let query = "rust ownership".to_string();
+        let expected = cosine.search(query.clone(), 4).unwrap();
+        let actual = mixed.search(query, 4).unwrap();
+        for ((text, score), (expected_text, expected_score)) in actual.iter().zip(&expected) {
+            assert_eq!(text, expected_text);
+            assert!((score - expected_score).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_score_rounding() {
+        let raw = MemoryVectorStore::new();
+        let rounded = MemoryVectorStore::new().with_score_rounding(2);
+        for store in [&raw, &rounded] {
+            store
+                .add("Rust has ownership".to_string(), serde_json::json!({}))
+                .unwrap();
+        }
+
+        let query = "rust ownership rules".to_string();
+        let raw_score = raw.search(query.clone(), 1).unwrap()[0].1;
+        let score = rounded.search(query, 1).unwrap()[0].1;
+        assert_eq!(score, (raw_score * 100.0).round() / 100.0);
+        assert!((score * 100.0 - (score * 100.0).round()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_recency_boost_favours_fresh_documents() {
+        let day = 24 * 60 * 60;
+        let now = 1_700_000_000;
+        let clock = Arc::new(MockClock::at(UNIX_EPOCH + Duration::from_secs(now)));
+        let store = |boost: bool| {
+            let store = MemoryVectorStore::new().with_clock(clock.clone());
+            let store = if boost {
+                store.with_recency_boost("updated_at", Duration::from_secs(30 * day))
+            } else {
+                store
+            };
+            let docs = [
+                (
+                    "rust ownership rules",
+                    serde_json::json!({"updated_at": now - 60 * day}),
+                ),
+                (
+                    "rust ownership rules explained",
+                    serde_json::json!({"updated_at": now}),
+                ),
+                ("rust borrowing notes", serde_json::json!({})),
+            ];
+            for (text, metadata) in docs {
+                store.add(text.to_string(), metadata).unwrap();
+            }
+            store
+        };
+
+        let query = "rust ownership rules".to_string();
+        let plain = store(false).search(query.clone(), 3).unwrap();
+        assert_eq!(plain[0].0, "rust ownership rules");
+        let boosted = store(true).search(query, 3).unwrap();
+        assert_eq!(boosted[0].0, "rust ownership rules explained");
+        let score = |hits: &[(String, f32)], text: &str| {
+            hits.iter().find(|(hit, _)| hit == text).unwrap().1
+        };
+        // Two half-lives old, a quarter of the score; undated is untouched
+        let stale = score(&boosted, "rust ownership rules");
+        assert!((stale - score(&plain, "rust ownership rules") / 4.0).abs() < 1e-3);
+        assert_eq!(
+            score(&boosted, "rust borrowing notes"),
+            score(&plain, "rust borrowing notes")
+        );
+
+        // A half-life later the fresh document has lost half its score
+        clock.advance(Duration::from_secs(30 * day));
+        let aged = store(true)
+            .search("rust ownership rules".to_string(), 3)
+            .unwrap();
+        let fresh = score(&plain, "rust ownership rules explained");
+        assert!((score(&aged, "rust ownership rules explained") - fresh / 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_zero_recency_half_life_is_clamped() {
+        let clock = Arc::new(MockClock::at(UNIX_EPOCH + Duration::from_secs(1000)));
+        let store = MemoryVectorStore::new()
+            .with_clock(clock)
+            .with_recency_boost("updated_at", Duration::ZERO);
+        store
+            .add(
+                "rust ownership rules".to_string(),
+                serde_json::json!({"updated_at": 999}),
+            )
+            .unwrap();
+        store
+            .add(
+                "rust ownership".to_string(),
+                serde_json::json!({"updated_at": 1000}),
+            )
+            .unwrap();
+
+        let hits = store.search("rust ownership rules".to_string(), 2).unwrap();
+        assert!(hits.iter().all(|(_, score)| score.is_finite()));
+        assert_eq!(hits[0].0, "rust ownership");
+        // One second old is one half-life
+        let stale = hits.iter().find(|(text, _)| text == "rust ownership rules");
+        assert!(stale.unwrap().1 > 0.0);
+    }
+
+    #[test]
+    fn test_default_metadata_is_merged_under_document_metadata() {
+        let store = MemoryVectorStore::new().with_default_metadata(serde_json::json!({
+            "source": "wiki",
+            "ingested_at": 1700000000,
+            "tags": {"lang": "en"},
+        }));
+        store
+            .add(
+                "rust ownership".to_string(),
+                serde_json::json!({"source": "blog", "tags": {"topic": "rust"}}),
+            )
+            .unwrap();
+        store
+            .add("rust borrowing".to_string(), serde_json::Value::Null)
+            .unwrap();
+
+        let documents = store.documents.read().unwrap();
+        assert_eq!(
+            documents[0].metadata,
+            serde_json::json!({
+                "source": "blog",
+                "ingested_at": 1700000000,
+                "tags": {"topic": "rust"},
+            })
+        );
+        assert_eq!(documents[1].metadata["source"], "wiki");
+    }
+
+    #[test]
+    fn test_upsert_is_idempotent_by_content() {
+        let store = MemoryVectorStore::new();
+        let id = store
+            .upsert(
+                "Rust has  ownership\n".to_string(),
+                serde_json::json!({"v": 1}),
+            )
+            .unwrap();
+        assert_eq!(id, content_id(None, "Rust has ownership"));
+        assert_eq!(id.len(), 16);
+        store
+            .add("Rust has ownership".to_string(), serde_json::json!({}))
+            .unwrap();
+        let again = store
+            .upsert(
+                "Rust has ownership".to_string(),
+                serde_json::json!({"v": 2}),
+            )
+            .unwrap();
+        assert_eq!(again, id);
+
+        let documents = store.documents.read().unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].metadata["v"], 2);
+        assert_ne!(content_id(Some("docs"), "Rust has ownership"), id);
+        assert_ne!(content_id(None, "rust has ownership"), id);
+    }
+
+    #[test]
+    fn test_hybrid_search_finds_exact_terms() {
+        let docs = [
+            "error handling guide",
+            "parser internals",
+            "error code E1234: missing key",
+        ];
+        let store = |hybrid: HybridSearch| {
+            let store = MemoryVectorStore::with_embedder(Box::new(BlindEmbedder))
+                .with_hybrid_search(hybrid);
+            for doc in docs {
+                store.add(doc.to_string(), serde_json::json!({})).unwrap();
+            }
+            store
+        };
+
+        let vector_only = store(HybridSearch::new(1.0))
+            .search("E1234".to_string(), 3)
+            .unwrap();
+        assert_eq!(vector_only[0], (docs[0].to_string(), 1.0));
+
+        let hybrid = store(HybridSearch::new(0.5))
+            .search("E1234".to_string(), 3)
+            .unwrap();
+        assert_eq!(hybrid[0].0, docs[2]);
+        assert_eq!(hybrid[0].1, 1.0);
+        assert_eq!(hybrid[1].1, 0.5);
+
+        let rrf =
+            store(HybridSearch::new(0.3).with_fusion(FusionMethod::ReciprocalRank { k: 60.0 }))
+                .search("E1234".to_string(), 3)
+                .unwrap();
+        assert_eq!(rrf[0].0, docs[2]);
+    }
 }
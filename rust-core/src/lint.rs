@@ -0,0 +1,111 @@
+//! Prompt linting - catch prompt assembly problems before they cost tokens
+
+/// Problem a linter found in a prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintIssue {
+    /// Reported in `AgentResponse.warnings`; the run continues
+    Warning(String),
+    /// Aborts the run with `AgentError::Validation`
+    Error(String),
+}
+
+/// Linter trait
+///
+/// Runs on every assembled prompt before it is sent to the provider.
+pub trait PromptLinter: Send + Sync {
+    fn lint(&self, prompt: &str) -> Vec<LintIssue>;
+}
+
+/// Warns about section headers (lines ending in `:`) with nothing under them
+///
+/// Usually a template whose variable was never filled in.
+#[derive(Debug, Clone, Default)]
+pub struct EmptySectionLinter;
+
+impl PromptLinter for EmptySectionLinter {
+    fn lint(&self, prompt: &str) -> Vec<LintIssue> {
+        let lines: Vec<&str> = prompt.lines().collect();
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(i, line)| {
+                let line = line.trim();
+                line.len() > 1
+                    && line.ends_with(':')
+                    && lines.get(i + 1).is_none_or(|next| next.trim().is_empty())
+            })
+            .map(|(_, line)| {
+                LintIssue::Warning(format!("prompt section {:?} is empty", line.trim()))
+            })
+            .collect()
+    }
+}
+
+/// Flags prompts longer than `max_chars` characters
+#[derive(Debug, Clone)]
+pub struct MaxLengthLinter {
+    max_chars: usize,
+    warn_only: bool,
+}
+
+impl MaxLengthLinter {
+    /// Fail prompts over `max_chars`
+    pub fn new(max_chars: usize) -> Self {
+        Self {
+            max_chars,
+            warn_only: false,
+        }
+    }
+
+    /// Only warn instead of failing
+    pub fn warn_only(mut self) -> Self {
+        self.warn_only = true;
+        self
+    }
+}
+
+impl PromptLinter for MaxLengthLinter {
+    fn lint(&self, prompt: &str) -> Vec<LintIssue> {
+        let len = prompt.chars().count();
+        if len <= self.max_chars {
+            return Vec::new();
+        }
+        let message = format!(
+            "prompt is {} characters, over the budget of {}",
+            len, self.max_chars
+        );
+        vec![if self.warn_only {
+            LintIssue::Warning(message)
+        } else {
+            LintIssue::Error(message)
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_linters() {
+        let prompt = "Context:\n\nTask: What is Rust?\nNotes:";
+        assert_eq!(
+            EmptySectionLinter.lint(prompt),
+            [
+                LintIssue::Warning("prompt section \"Context:\" is empty".to_string()),
+                LintIssue::Warning("prompt section \"Notes:\" is empty".to_string()),
+            ]
+        );
+        assert!(EmptySectionLinter.lint("Context:\n- rust\n").is_empty());
+
+        assert!(MaxLengthLinter::new(100).lint(prompt).is_empty());
+        assert!(matches!(
+            MaxLengthLinter::new(10).lint(prompt)[..],
+            [LintIssue::Error(_)]
+        ));
+        assert!(matches!(
+            MaxLengthLinter::new(10).warn_only().lint(prompt)[..],
+            [LintIssue::Warning(_)]
+        ));
+    }
+}
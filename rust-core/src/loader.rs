@@ -0,0 +1,289 @@
+//! Document loading - the ingestion front-end for vector stores
+
+use crate::chunking::chunk_text;
+use crate::{AgentError, CancellationToken, VectorStore};
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+
+/// Document text plus metadata describing where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedDocument {
+    pub text: String,
+    /// JSON object; loaders always set a `source` field naming what the
+    /// text was loaded from
+    pub metadata: serde_json::Value,
+}
+
+impl LoadedDocument {
+    /// Split into chunks of at most `max_chars` and add them to `store`
+    ///
+    /// `ingest_documents` for this document alone, in a single batch.
+    /// Returns the number of chunks added.
+    pub fn ingest(&self, store: &dyn VectorStore, max_chars: usize) -> Result<usize, AgentError> {
+        let cancel = CancellationToken::new();
+        ingest_documents(
+            store,
+            std::slice::from_ref(self),
+            max_chars,
+            usize::MAX,
+            &cancel,
+        )
+    }
+}
+
+/// Chunk `documents` and add them to `store`, `batch_size` chunks at a time
+///
+/// Each chunk gets its document's metadata plus its `chunk` index; metadata
+/// that is neither an object nor `null` is an `AgentError::Validation`,
+/// reported before anything is added. Batches
+/// go through `VectorStore::add_batch`, so `MemoryVectorStore` embeds each
+/// with `Embedder::embed_batch`. `cancel` is checked before and during
+/// every batch; a cancelled job returns `AgentError::Cancelled` with the
+/// number of chunks already added, which stay in the store. Returns the
+/// number of chunks added.
+pub fn ingest_documents(
+    store: &dyn VectorStore,
+    documents: &[LoadedDocument],
+    max_chars: usize,
+    batch_size: usize,
+    cancel: &CancellationToken,
+) -> Result<usize, AgentError> {
+    let mut chunks: Vec<(String, serde_json::Value)> = Vec::new();
+    for document in documents {
+        let base = match &document.metadata {
+            serde_json::Value::Null => serde_json::Map::new(),
+            serde_json::Value::Object(metadata) => metadata.clone(),
+            _ => {
+                return Err(AgentError::Validation(
+                    "document metadata must be a JSON object or null".to_string(),
+                ))
+            }
+        };
+        for (i, chunk) in chunk_text(&document.text, max_chars)
+            .into_iter()
+            .enumerate()
+        {
+            let mut metadata = base.clone();
+            metadata.insert("chunk".to_string(), i.into());
+            chunks.push((chunk, metadata.into()));
+        }
+    }
+
+    let stopped = |added: usize| {
+        format!(
+            "ingestion stopped after {} of {} chunks",
+            added,
+            chunks.len()
+        )
+    };
+    let mut added = 0;
+    for batch in chunks.chunks(batch_size.max(1)) {
+        cancel.check(|| stopped(added))?;
+        added += match store.add_batch(batch.to_vec(), cancel) {
+            Err(AgentError::Cancelled(_)) => return Err(AgentError::Cancelled(stopped(added))),
+            result => result?,
+        };
+    }
+    Ok(added)
+}
+
+/// Loader trait
+///
+/// Turns a source (a path, a URL, ...) into documents. Loaders for other
+/// formats can be added next to `FileLoader`.
+#[async_trait]
+pub trait DocumentLoader: Send + Sync {
+    async fn load(&self, source: &str) -> Result<Vec<LoadedDocument>, AgentError>;
+}
+
+/// Loads UTF-8 text files
+///
+/// A file source yields one document; a directory yields one per file
+/// below it, in path order. Files that are not valid UTF-8 fail the load.
+#[derive(Debug, Clone, Default)]
+pub struct FileLoader {
+    extensions: Vec<String>,
+}
+
+impl FileLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only load directory entries with extension `extension` (e.g. `md`)
+    ///
+    /// May be called more than once; by default every file is loaded.
+    /// Explicit file sources are always loaded.
+    pub fn with_extension(mut self, extension: impl Into<String>) -> Self {
+        self.extensions.push(extension.into());
+        self
+    }
+
+    fn wanted(&self, path: &Path) -> bool {
+        self.extensions.is_empty()
+            || path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| self.extensions.iter().any(|wanted| wanted == e))
+    }
+
+    /// Files to load for `path`, recursing into directories
+    async fn files(&self, path: &Path) -> Result<Vec<PathBuf>, AgentError> {
+        let mut files = Vec::new();
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(path) = pending.pop() {
+            if !tokio::fs::metadata(&path).await?.is_dir() {
+                files.push(path);
+                continue;
+            }
+            let mut entries = tokio::fs::read_dir(&path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                } else if self.wanted(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+#[async_trait]
+impl DocumentLoader for FileLoader {
+    async fn load(&self, source: &str) -> Result<Vec<LoadedDocument>, AgentError> {
+        let mut documents = Vec::new();
+        for path in self.files(Path::new(source)).await? {
+            let bytes = tokio::fs::read(&path).await?;
+            let text = String::from_utf8(bytes).map_err(|_| {
+                AgentError::ParseError(format!("{} is not UTF-8 text", path.display()))
+            })?;
+            documents.push(LoadedDocument {
+                text,
+                metadata: serde_json::json!({"source": path.display().to_string()}),
+            });
+        }
+        Ok(documents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Embedder, HashEmbedder, MemoryVectorStore};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_file_loader_reads_directory_tree() {
+        let root = std::env::temp_dir().join(format!("agent-core-loader-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("guides")).unwrap();
+        std::fs::write(root.join("a.md"), "Rust is fast").unwrap();
+        std::fs::write(root.join("guides/b.md"), "Ownership rules").unwrap();
+        std::fs::write(root.join("image.png"), [0xff, 0xfe]).unwrap();
+
+        let loader = FileLoader::new().with_extension("md");
+        let documents = loader.load(root.to_str().unwrap()).await.unwrap();
+        let texts: Vec<&str> = documents.iter().map(|d| d.text.as_str()).collect();
+        assert_eq!(texts, ["Rust is fast", "Ownership rules"]);
+        assert!(documents[1].metadata["source"]
+            .as_str()
+            .unwrap()
+            .ends_with("b.md"));
+
+        let png = root.join("image.png");
+        assert!(matches!(
+            FileLoader::new().load(png.to_str().unwrap()).await,
+            Err(AgentError::ParseError(_))
+        ));
+
+        let store = MemoryVectorStore::new();
+        assert_eq!(documents[0].ingest(&store, 5).unwrap(), 3);
+        let chunks =
+            documents[0].ingest(&store, 5).unwrap() + documents[1].ingest(&store, 5).unwrap();
+        let cancel = CancellationToken::new();
+        assert_eq!(
+            ingest_documents(&store, &documents, 5, 2, &cancel).unwrap(),
+            chunks
+        );
+        cancel.cancel();
+        assert!(matches!(
+            ingest_documents(&store, &documents, 5, 2, &cancel),
+            Err(AgentError::Cancelled(_))
+        ));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Embedder recording the size of every batch it embeds
+    struct BatchRecorder(Arc<Mutex<Vec<usize>>>);
+
+    impl Embedder for BatchRecorder {
+        fn dimensions(&self) -> usize {
+            8
+        }
+
+        fn embed(&self, text: &str) -> Result<Vec<f32>, AgentError> {
+            HashEmbedder::new(8).embed(text)
+        }
+
+        fn embed_batch(
+            &self,
+            texts: &[&str],
+            cancel: &CancellationToken,
+        ) -> Result<Vec<Vec<f32>>, AgentError> {
+            self.0.lock().unwrap().push(texts.len());
+            cancel.check(|| "cancelled mid-batch".to_string())?;
+            texts.iter().map(|text| self.embed(text)).collect()
+        }
+    }
+
+    #[test]
+    fn test_ingestion_embeds_in_batches() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let store = MemoryVectorStore::with_embedder(Box::new(BatchRecorder(batches.clone())));
+        let document = LoadedDocument {
+            text: "alpha beta gamma delta epsilon".to_string(),
+            metadata: serde_json::json!({"source": "greek.txt"}),
+        };
+
+        let cancel = CancellationToken::new();
+        let added =
+            ingest_documents(&store, std::slice::from_ref(&document), 11, 2, &cancel).unwrap();
+        assert_eq!(added, 3);
+        assert_eq!(*batches.lock().unwrap(), [2, 1]);
+        let hits = store.search("gamma".to_string(), 1).unwrap();
+        assert!(hits[0].0.contains("gamma"));
+
+        // One batch for the whole document
+        assert_eq!(document.ingest(&store, 11).unwrap(), 3);
+        assert_eq!(*batches.lock().unwrap(), [2, 1, 3]);
+    }
+
+    #[test]
+    fn test_ingestion_checks_document_metadata() {
+        let store = MemoryVectorStore::new();
+        let document = |metadata| LoadedDocument {
+            text: "alpha beta".to_string(),
+            metadata,
+        };
+
+        assert_eq!(
+            document(serde_json::Value::Null)
+                .ingest(&store, 100)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            store.documents.read().unwrap()[0].metadata,
+            serde_json::json!({"chunk": 0})
+        );
+
+        for metadata in [serde_json::json!("greek.txt"), serde_json::json!([1])] {
+            let error = document(metadata).ingest(&store, 100).unwrap_err();
+            assert!(matches!(error, AgentError::Validation(_)));
+        }
+        assert_eq!(store.documents.read().unwrap().len(), 1);
+    }
+}
@@ -1,40 +1,404 @@
 //! Agent Server - High-performance API server
 
-use agent_core::{AgentRequest, AgentResponse, OpenAIProvider, ReActAgent, MemoryVectorStore};
+use agent_core::{
+    AgentError, AgentRequest, AnthropicProvider, ApprovalQueue, AuditTransport, ClientMessage,
+    GeminiProvider, HttpTransport, JsonlAuditSink, JsonlSink, LLMProvider, MemoryVectorStore,
+    Metrics, OpenAIProvider, ProviderRouter, ReActAgent, ReqwestTransport, RunOptions,
+    ServerMessage, StreamEvent, DEBUG_SPAN,
+};
+use futures::{SinkExt, StreamExt};
 use std::convert::Infallible;
 use std::sync::Arc;
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use warp::http::header::{HeaderMap, HeaderName};
+use warp::http::StatusCode;
+use warp::ws::{Message, WebSocket};
 use warp::Filter;
 
+/// Agent failure surfaced as a warp rejection
+#[derive(Debug)]
+struct AgentRejection(AgentError);
+
+impl warp::reject::Reject for AgentRejection {}
+
+/// Request body naming a provider that is not configured
+#[derive(Debug)]
+struct UnknownProvider(String);
+
+impl warp::reject::Reject for UnknownProvider {}
+
+/// JSON `AgentRequest` body, rejected if it names a provider missing from
+/// `providers`
+fn agent_request(
+    providers: Arc<Vec<String>>,
+) -> impl Filter<Extract = (AgentRequest,), Error = warp::Rejection> + Clone {
+    warp::body::json().and_then(move |request: AgentRequest| {
+        let providers = providers.clone();
+        async move {
+            match &request.provider {
+                Some(name) if !providers.contains(name) => {
+                    Err(warp::reject::custom(UnknownProvider(name.clone())))
+                }
+                _ => Ok(request),
+            }
+        }
+    })
+}
+
+/// Render rejections as JSON error bodies
+///
+/// A run stopped by its token budget also returns its partial `thoughts`.
+async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
+    let (status, message) = if let Some(AgentRejection(e)) = err.find() {
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    } else if let Some(UnknownProvider(name)) = err.find() {
+        let message = format!("unknown provider: {}", name);
+        (StatusCode::BAD_REQUEST, message)
+    } else if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "not found".to_string())
+    } else {
+        (StatusCode::BAD_REQUEST, format!("{:?}", err))
+    };
+
+    let mut body = serde_json::json!({"error": message});
+    if let Some(AgentRejection(AgentError::TokenBudgetExceeded { thoughts, .. })) = err.find() {
+        body["thoughts"] = serde_json::json!(thoughts);
+    }
+    Ok(warp::reply::with_status(warp::reply::json(&body), status))
+}
+
+/// Incoming headers forwarded to the provider, from `AGENT_PASSTHROUGH_HEADERS`
+///
+/// A comma-separated list of header names, e.g. `authorization,x-tenant-id`
+/// for bring-your-own-key gateways. Nothing is forwarded by default.
+fn passthrough_headers() -> Vec<HeaderName> {
+    std::env::var("AGENT_PASSTHROUGH_HEADERS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect()
+}
+
+/// Run options carrying the allowed headers of one incoming request
+///
+/// `X-Debug: true` (or `1`) turns on `RunOptions::debug`.
+fn run_options(incoming: &HeaderMap, allowed: &[HeaderName]) -> RunOptions {
+    let mut options = RunOptions::default();
+    for name in allowed {
+        if let Some(value) = incoming.get(name) {
+            options.headers.insert(name.clone(), value.clone());
+        }
+    }
+    options.debug = incoming
+        .get("x-debug")
+        .is_some_and(|value| value == "true" || value == "1");
+    options
+}
+
+/// `run_options` plus the per-run settings of an HTTP request body
+fn request_options(
+    incoming: &HeaderMap,
+    allowed: &[HeaderName],
+    request: &AgentRequest,
+) -> RunOptions {
+    let mut options = run_options(incoming, allowed);
+    options.locale = request.locale.clone();
+    options.provider = request.provider.clone();
+    options.model = request.model.clone();
+    options.safety_settings = request.safety_settings.clone();
+    options.debug |= request.debug;
+    options
+}
+
+/// Send `message` to the client, returning false once the socket is gone
+async fn send(
+    socket: &mut futures::stream::SplitSink<WebSocket, Message>,
+    message: &ServerMessage,
+) -> bool {
+    let json = serde_json::to_string(message).unwrap_or_default();
+    socket.send(Message::text(json)).await.is_ok()
+}
+
+/// Next client message; `None` once the socket is closed
+///
+/// Frames that are not valid client messages are answered with an
+/// `error` message and skipped.
+async fn receive(
+    incoming: &mut futures::stream::SplitStream<WebSocket>,
+    outgoing: &mut futures::stream::SplitSink<WebSocket, Message>,
+) -> Option<ClientMessage> {
+    while let Some(Ok(frame)) = incoming.next().await {
+        if frame.is_close() {
+            return None;
+        }
+        let Ok(text) = frame.to_str() else {
+            continue;
+        };
+        match serde_json::from_str(text) {
+            Ok(message) => return Some(message),
+            Err(e) => {
+                let message = format!("invalid message: {}", e);
+                if !send(outgoing, &ServerMessage::Error { message }).await {
+                    return None;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Drive one interactive run over a WebSocket
+///
+/// The run's events, including its `lifecycle` events, are sent as `event`
+/// messages, as on `/api/agent/stream`.
+async fn agent_socket(agent: Arc<ReActAgent>, mut options: RunOptions, socket: WebSocket) {
+    let (mut outgoing, mut incoming) = socket.split();
+    options.lifecycle_events = true;
+    // Approval requests go out as events and are answered with `approve_tool`
+    let approvals = Arc::new(ApprovalQueue::new());
+    options.approver = Some(approvals.clone());
+
+    let task = loop {
+        match receive(&mut incoming, &mut outgoing).await {
+            Some(ClientMessage::Start { task, locale }) => {
+                options.locale = locale;
+                break task;
+            }
+            Some(_) => {
+                let message = "send a start message first".to_string();
+                if !send(&mut outgoing, &ServerMessage::Error { message }).await {
+                    return;
+                }
+            }
+            None => return,
+        }
+    };
+
+    let (tx, mut events) = tokio::sync::mpsc::unbounded_channel();
+    let run = tokio::spawn(async move { agent.execute_stream_with(task, options, tx).await });
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+                let last = matches!(event, StreamEvent::Done(_) | StreamEvent::Error { .. });
+                if !send(&mut outgoing, &ServerMessage::Event { event: Box::new(event) }).await || last {
+                    break;
+                }
+            }
+            message = receive(&mut incoming, &mut outgoing) => match message {
+                Some(ClientMessage::Cancel) => {
+                    run.abort();
+                    send(&mut outgoing, &ServerMessage::Cancelled).await;
+                    break;
+                }
+                Some(ClientMessage::Start { .. }) => {
+                    let message = "a run is already in progress".to_string();
+                    send(&mut outgoing, &ServerMessage::Error { message }).await;
+                }
+                Some(ClientMessage::ApproveTool { call_id, approved }) => {
+                    if !approvals.resolve(&call_id, approved) {
+                        let message = format!("no tool call {} is waiting for approval", call_id);
+                        send(&mut outgoing, &ServerMessage::Error { message }).await;
+                    }
+                }
+                // Nobody is listening any more
+                None => {
+                    run.abort();
+                    return;
+                }
+            },
+        }
+    }
+    let _ = outgoing.close().await;
+}
+
 #[tokio::main]
 async fn main() {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    // Log at info level, and at debug level inside runs that asked for it
+    let filter = tracing_subscriber::filter::dynamic_filter_fn(|metadata, context| {
+        *metadata.level() <= tracing::Level::INFO
+            || metadata.name() == DEBUG_SPAN
+            || context
+                .lookup_current()
+                .is_some_and(|span| span.scope().any(|span| span.name() == DEBUG_SPAN))
+    });
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(filter))
+        .init();
 
     // Create agent
-    let provider = Box::new(OpenAIProvider::new(std::env::var("OPENAI_API_KEY").unwrap_or_default()));
+    let mut transport: Arc<dyn HttpTransport> = Arc::new(ReqwestTransport::new());
+    // Optional JSON-lines audit log of every provider exchange
+    if let Ok(path) = std::env::var("AGENT_AUDIT_LOG") {
+        match JsonlAuditSink::create(&path) {
+            Ok(sink) => transport = Arc::new(AuditTransport::new(transport, Arc::new(sink))),
+            Err(e) => tracing::warn!(path, error = %e, "could not open audit log"),
+        }
+    }
+    let openai = OpenAIProvider::new(std::env::var("OPENAI_API_KEY").unwrap_or_default())
+        .with_transport(transport.clone());
+    // Requests pick a provider by name and default to OpenAI
+    let mut provider = ProviderRouter::new("openai", Box::new(openai));
+    if let Ok(api_key) = std::env::var("ANTHROPIC_API_KEY") {
+        let anthropic = AnthropicProvider::new(api_key).with_transport(transport.clone());
+        provider = provider.with_provider("anthropic", Box::new(anthropic));
+    }
+    if let Ok(api_key) = std::env::var("GEMINI_API_KEY") {
+        let gemini = GeminiProvider::new(api_key).with_transport(transport);
+        provider = provider.with_provider("gemini", Box::new(gemini));
+    }
+    let providers = Arc::new(provider.names());
+    // Fail fast on configuration the first request would trip over
+    if let Err(e) = provider.validate().await {
+        tracing::error!(error = %e, "invalid provider configuration");
+        std::process::exit(1);
+    }
+    let provider = Box::new(provider);
     let vector_store = Box::new(MemoryVectorStore::new());
-    let agent = Arc::new(ReActAgent::new(provider, vector_store));
+    let metrics = Arc::new(Metrics::new());
+    let mut agent = ReActAgent::new(provider, vector_store).with_metrics(metrics.clone());
+    // Optional JSON-lines log of agent events
+    if let Ok(path) = std::env::var("AGENT_EVENT_LOG") {
+        match JsonlSink::create(&path) {
+            Ok(sink) => agent = agent.with_event_sink(Arc::new(sink)),
+            Err(e) => tracing::warn!(path, error = %e, "could not open agent event log"),
+        }
+    }
+    if let Err(e) = agent.init().await {
+        tracing::error!(error = %e, "provider initialization failed");
+        std::process::exit(1);
+    }
+    let agent = Arc::new(agent);
+    let passthrough = Arc::new(passthrough_headers());
 
     // Routes
     let health = warp::path!("health")
         .map(|| warp::reply::json(&serde_json::json!({"status": "healthy"})));
 
+    let metrics_route = warp::path!("metrics").map(move || {
+        warp::reply::with_header(
+            metrics.render(),
+            "content-type",
+            "text/plain; version=0.0.4",
+        )
+    });
+
+    let inflight_agent = agent.clone();
+    let inflight_route = warp::path!("api" / "agent" / "inflight")
+        .and(warp::get())
+        .map(move || warp::reply::json(&inflight_agent.inflight()));
+
+    let stream_agent = agent.clone();
+    let stream_passthrough = passthrough.clone();
+    let stream_route = warp::path!("api" / "agent" / "stream")
+        .and(warp::post())
+        .and(warp::header::headers_cloned())
+        .and(agent_request(providers.clone()))
+        .map(move |headers: HeaderMap, req: AgentRequest| {
+            let agent = stream_agent.clone();
+            let mut options = request_options(&headers, &stream_passthrough, &req);
+            options.lifecycle_events = true;
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            // Failures reach the client as a final `error` event
+            tokio::spawn(async move {
+                let _ = agent.execute_stream_with(req.task, options, tx).await;
+            });
+
+            let events = futures::stream::unfold(rx, |mut rx| async move {
+                let event: StreamEvent = rx.recv().await?;
+                let frame = warp::sse::Event::default()
+                    .event(event.name())
+                    .json_data(&event)
+                    .unwrap_or_default();
+                Some((Ok::<_, Infallible>(frame), rx))
+            });
+            warp::sse::reply(warp::sse::keep_alive().stream(events))
+        });
+
+    let ws_agent = agent.clone();
+    let ws_passthrough = passthrough.clone();
+    let ws_route = warp::path!("ws" / "agent")
+        .and(warp::ws())
+        .and(warp::header::headers_cloned())
+        .map(move |ws: warp::ws::Ws, headers: HeaderMap| {
+            let agent = ws_agent.clone();
+            let options = run_options(&headers, &ws_passthrough);
+            ws.on_upgrade(move |socket| agent_socket(agent, options, socket))
+        });
+
     let agent_route = warp::path!("api" / "agent")
         .and(warp::post())
-        .and(warp::body::json())
-        .and_then(move |req: AgentRequest| {
+        .and(warp::header::headers_cloned())
+        .and(agent_request(providers))
+        .and_then(move |headers: HeaderMap, req: AgentRequest| {
             let agent = agent.clone();
+            let options = request_options(&headers, &passthrough, &req);
             async move {
-                let response = agent.execute(req.task).await;
+                let response = agent.execute_with(req.task, options).await;
                 match response {
                     Ok(resp) => Ok(warp::reply::json(&resp)),
-                    Err(e) => Err(warp::reject::custom(e)),
+                    Err(e) => Err(warp::reject::custom(AgentRejection(e))),
                 }
             }
         });
 
-    let routes = health.or(agent_route);
+    let routes = health
+        .or(metrics_route)
+        .or(inflight_route)
+        .or(stream_route)
+        .or(ws_route)
+        .or(agent_route)
+        .recover(handle_rejection);
 
     println!("🚀 Rust Agent Server starting on port 3030");
     warp::serve(routes).run(([0, 0, 0, 0], 3030)).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agent_core::MockProvider;
+
+    #[tokio::test]
+    async fn test_socket_streams_lifecycle_events() {
+        let agent = Arc::new(ReActAgent::new(
+            Box::new(MockProvider::new()),
+            Box::new(MemoryVectorStore::new()),
+        ));
+        let route = warp::ws().map(move |ws: warp::ws::Ws| {
+            let agent = agent.clone();
+            ws.on_upgrade(move |socket| agent_socket(agent, RunOptions::default(), socket))
+        });
+        let mut client = warp::test::ws().handshake(route).await.unwrap();
+        client
+            .send_text(r#"{"type": "start", "task": "Hello"}"#)
+            .await;
+
+        let mut frames = Vec::new();
+        while let Ok(frame) = client.recv().await {
+            let message: serde_json::Value = serde_json::from_str(frame.to_str().unwrap()).unwrap();
+            let event = &message["event"];
+            frames.push(match event["type"].as_str().unwrap() {
+                "lifecycle" => format!("lifecycle {}", event["event"]["type"].as_str().unwrap()),
+                other => other.to_string(),
+            });
+            if event["type"] == "done" {
+                break;
+            }
+        }
+        assert_eq!(
+            frames,
+            [
+                "accepted",
+                "lifecycle request_received",
+                "lifecycle step",
+                "delta",
+                "thought",
+                "lifecycle response",
+                "done",
+            ]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Metrics - counters and histograms exposed in Prometheus text format
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Cumulative histogram with fixed upper bounds
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: Vec<u64>,
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    /// Histogram with the given bucket upper bounds; `+Inf` is implicit
+    pub fn new(bounds: &[u64]) -> Self {
+        let mut bounds = bounds.to_vec();
+        bounds.sort_unstable();
+        bounds.dedup();
+        Self {
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            bounds,
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            if value <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, name: &str, help: &str, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                bound,
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count();
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum.load(Ordering::Relaxed));
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+/// Usage of one tool so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolStats {
+    pub calls: u64,
+    pub failures: u64,
+    /// Total time spent in the tool, in milliseconds
+    pub duration_ms: u64,
+}
+
+impl ToolStats {
+    /// Calls that did not fail
+    ///
+    /// A snapshot taken while a failed call is being recorded may not see
+    /// that call's `calls` increment yet, so this stops at zero.
+    pub fn successes(&self) -> u64 {
+        self.calls.saturating_sub(self.failures)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ToolCounters {
+    calls: AtomicU64,
+    failures: AtomicU64,
+    duration_ms: AtomicU64,
+}
+
+/// Per-tool call counters, safe to update from concurrent runs
+///
+/// Updating an already seen tool only takes a read lock and atomic adds.
+#[derive(Debug, Default)]
+pub struct ToolUsage {
+    tools: RwLock<BTreeMap<String, ToolCounters>>,
+}
+
+impl ToolUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, tool: &str, success: bool, duration: Duration) {
+        if !self.tools.read().unwrap().contains_key(tool) {
+            self.tools
+                .write()
+                .unwrap()
+                .entry(tool.to_string())
+                .or_default();
+        }
+        let tools = self.tools.read().unwrap();
+        let counters = &tools[tool];
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            counters.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        counters
+            .duration_ms
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Current stats of every tool that has been called, by name
+    pub fn snapshot(&self) -> BTreeMap<String, ToolStats> {
+        self.tools
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, counters)| {
+                let stats = ToolStats {
+                    calls: counters.calls.load(Ordering::Relaxed),
+                    failures: counters.failures.load(Ordering::Relaxed),
+                    duration_ms: counters.duration_ms.load(Ordering::Relaxed),
+                };
+                (name.clone(), stats)
+            })
+            .collect()
+    }
+
+    fn render(&self, out: &mut String) {
+        let stats = self.snapshot();
+        let _ = writeln!(out, "# HELP agent_tool_calls_total Tool calls by outcome");
+        let _ = writeln!(out, "# TYPE agent_tool_calls_total counter");
+        for (name, stats) in &stats {
+            let name = escape_label(name);
+            for (outcome, count) in [("success", stats.successes()), ("failure", stats.failures)] {
+                let _ = writeln!(
+                    out,
+                    "agent_tool_calls_total{{tool=\"{}\",outcome=\"{}\"}} {}",
+                    name, outcome, count
+                );
+            }
+        }
+        let _ = writeln!(
+            out,
+            "# HELP agent_tool_duration_ms_total Time spent in tools in milliseconds"
+        );
+        let _ = writeln!(out, "# TYPE agent_tool_duration_ms_total counter");
+        for (name, stats) in &stats {
+            let _ = writeln!(
+                out,
+                "agent_tool_duration_ms_total{{tool=\"{}\"}} {}",
+                escape_label(name),
+                stats.duration_ms
+            );
+        }
+    }
+}
+
+/// Prometheus label value with backslashes and quotes escaped
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Agent metrics shared between the agent and the `/metrics` route
+#[derive(Debug)]
+pub struct Metrics {
+    /// Time to first token of streaming runs, in milliseconds
+    pub ttft_ms: Histogram,
+    /// Calls of every tool; agents given these metrics record into it
+    pub tools: Arc<ToolUsage>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            ttft_ms: Histogram::new(&[50, 100, 250, 500, 1000, 2500, 5000, 10000]),
+            tools: Arc::new(ToolUsage::new()),
+        }
+    }
+
+    /// Prometheus text exposition of every metric
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.ttft_ms.render(
+            "agent_ttft_ms",
+            "Time to first content delta of streaming runs in milliseconds",
+            &mut out,
+        );
+        self.tools.render(&mut out);
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_render() {
+        let histogram = Histogram::new(&[100, 10]);
+        histogram.observe(5);
+        histogram.observe(50);
+        histogram.observe(500);
+
+        let mut out = String::new();
+        histogram.render("latency_ms", "Latency", &mut out);
+        assert!(out.contains("# TYPE latency_ms histogram"));
+        assert!(out.contains("latency_ms_bucket{le=\"10\"} 1"));
+        assert!(out.contains("latency_ms_bucket{le=\"100\"} 2"));
+        assert!(out.contains("latency_ms_bucket{le=\"+Inf\"} 3"));
+        assert!(out.contains("latency_ms_sum 555"));
+        assert!(out.contains("latency_ms_count 3"));
+    }
+
+    #[test]
+    fn test_tool_usage_render() {
+        let metrics = Metrics::new();
+        metrics
+            .tools
+            .record("search", true, Duration::from_millis(30));
+        metrics
+            .tools
+            .record("search", false, Duration::from_millis(12));
+
+        let stats = metrics.tools.snapshot()["search"];
+        assert_eq!(stats.calls, 2);
+        assert_eq!(stats.successes(), 1);
+        assert_eq!(stats.duration_ms, 42);
+
+        let out = metrics.render();
+        assert!(out.contains("agent_tool_calls_total{tool=\"search\",outcome=\"success\"} 1"));
+        assert!(out.contains("agent_tool_calls_total{tool=\"search\",outcome=\"failure\"} 1"));
+        assert!(out.contains("agent_tool_duration_ms_total{tool=\"search\"} 42"));
+
+        let racing = ToolStats {
+            calls: 0,
+            failures: 1,
+            duration_ms: 0,
+        };
+        assert_eq!(racing.successes(), 0);
+    }
+}
@@ -0,0 +1,54 @@
+//! Mock provider - canned responses for tests and offline development
+
+use crate::{AgentError, AgentRequest, AgentResponse, LLMProvider, Thought};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Provider that answers without any network access
+///
+/// Queued responses are returned in order; once the queue is empty every
+/// call echoes the task. Every request is recorded for later inspection.
+#[derive(Default)]
+pub struct MockProvider {
+    responses: Mutex<VecDeque<AgentResponse>>,
+    requests: Mutex<Vec<AgentRequest>>,
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a response for the next unanswered call
+    pub fn with_response(self, response: AgentResponse) -> Self {
+        self.responses.lock().unwrap().push_back(response);
+        self
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<AgentRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LLMProvider for MockProvider {
+    async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+        self.requests.lock().unwrap().push(request.clone());
+
+        if let Some(response) = self.responses.lock().unwrap().pop_front() {
+            return Ok(response);
+        }
+
+        Ok(AgentResponse {
+            result: format!("Mock response for: {}", request.task),
+            thoughts: vec![Thought {
+                thought_type: "thought".to_string(),
+                content: format!("Analyzing: {}", request.task),
+            }],
+            provider: "mock".to_string(),
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,212 @@
+//! JSON patches - turn a growing JSON answer into incremental updates
+
+use serde_json::{Map, Value};
+
+/// Best-effort parse of the JSON object at the start of an unfinished answer
+///
+/// Text before the first `{` (prose, a code fence) is skipped, as is text
+/// after the brace that closes the object. Open strings
+/// and containers are closed; a trailing member that cannot be completed
+/// yet (a bare key, a half-written `true`) is left out until more text
+/// arrives. Returns `None` until an object has started.
+pub fn parse_partial_json(text: &str) -> Option<Value> {
+    let mut text = &text[text.find('{')?..];
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut depth = 0;
+    // Places the text can be cut and still be completed: before a comma
+    // and right after an opening bracket
+    let mut cuts = Vec::new();
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            ',' => cuts.push(i),
+            '{' | '[' => {
+                depth += 1;
+                cuts.push(i + 1);
+            }
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    text = &text[..=i];
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    std::iter::once(text.len())
+        .chain(cuts.into_iter().rev())
+        .find_map(|cut| serde_json::from_str(&close(&text[..cut])).ok())
+}
+
+/// `prefix` with its open string and containers closed
+fn close(prefix: &str) -> String {
+    let mut closed = prefix.to_string();
+    let mut open = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in prefix.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => open.push('}'),
+            '[' => open.push(']'),
+            '}' | ']' => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        if escaped {
+            closed.pop();
+        }
+        closed.push('"');
+    }
+    closed.extend(open.iter().rev());
+    closed
+}
+
+/// JSON Merge Patch (RFC 7396) turning object `old` into object `new`
+///
+/// Removed members become `null`, nested objects are diffed recursively
+/// and any other changed value is replaced whole. `None` when nothing
+/// changed. As in the RFC, a member set to `null` reads as removed.
+pub fn merge_patch(old: &Map<String, Value>, new: &Map<String, Value>) -> Option<Value> {
+    let mut patch = Map::new();
+    for key in old.keys().filter(|key| !new.contains_key(*key)) {
+        patch.insert(key.clone(), Value::Null);
+    }
+    for (key, value) in new {
+        match (old.get(key), value) {
+            (Some(old), _) if old == value => {}
+            (Some(Value::Object(old)), Value::Object(new)) => {
+                if let Some(nested) = merge_patch(old, new) {
+                    patch.insert(key.clone(), nested);
+                }
+            }
+            _ => {
+                patch.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    (!patch.is_empty()).then_some(Value::Object(patch))
+}
+
+/// Applies `patch` to `target` as a JSON Merge Patch, as a client would
+pub fn apply_merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("target was just made an object");
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            apply_merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Tracks a streamed answer and yields a merge patch whenever the object
+/// it describes changes
+#[derive(Debug, Default)]
+pub(crate) struct PatchStream {
+    text: String,
+    current: Map<String, Value>,
+}
+
+impl PatchStream {
+    pub(crate) fn push(&mut self, delta: &str) -> Option<Value> {
+        self.text.push_str(delta);
+        let Some(Value::Object(next)) = parse_partial_json(&self.text) else {
+            return None;
+        };
+        let patch = merge_patch(&self.current, &next);
+        self.current = next;
+        patch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_partial_json() {
+        assert_eq!(parse_partial_json("Sure: "), None);
+        assert_eq!(
+            parse_partial_json("```json\n{\"name\": \"Ada Lo"),
+            Some(json!({"name": "Ada Lo"}))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"name": "Ada", "tags": ["math", "comp"#),
+            Some(json!({"name": "Ada", "tags": ["math", "comp"]}))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"name": "Ada", "ali"#),
+            Some(json!({"name": "Ada"}))
+        );
+        assert_eq!(parse_partial_json(r#"{"ok": tr"#), Some(json!({})));
+    }
+
+    #[test]
+    fn test_text_after_the_object_is_ignored() {
+        let fenced = "```json\n{\"a\": 1, \"b\": {\"c\": \"}\"}}\n```";
+        assert_eq!(
+            parse_partial_json(fenced),
+            Some(json!({"a": 1, "b": {"c": "}"}}))
+        );
+
+        // A closing fence must not retract the last member
+        let mut stream = PatchStream::default();
+        let mut patches = Vec::new();
+        for chunk in ["```json\n{\"a\": 1, ", "\"b\": 2}", "\n```"] {
+            patches.extend(stream.push(chunk));
+        }
+        assert_eq!(patches, [json!({"a": 1}), json!({"b": 2})]);
+    }
+
+    #[test]
+    fn test_patches_rebuild_the_object() {
+        let answer = r#"{"name": "Ada", "address": {"city": "London"}, "age": 36}"#;
+        let mut stream = PatchStream::default();
+        let mut rebuilt = json!({});
+        let mut patches = 0;
+        // Feed the answer a few characters at a time
+        for chunk in answer.as_bytes().chunks(5) {
+            if let Some(patch) = stream.push(std::str::from_utf8(chunk).unwrap()) {
+                apply_merge_patch(&mut rebuilt, &patch);
+                patches += 1;
+            }
+        }
+        assert_eq!(rebuilt, serde_json::from_str::<Value>(answer).unwrap());
+        assert!(patches > 3);
+    }
+}
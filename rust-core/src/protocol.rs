@@ -0,0 +1,67 @@
+//! WebSocket protocol for interactive agent runs
+//!
+//! Both directions are JSON objects tagged by `type`. A client opens the
+//! socket, sends `start`, then receives `event` messages until the run's
+//! `done` or `error` event, or `cancelled`. Control messages may be sent
+//! at any point of the run.
+
+use crate::StreamEvent;
+use serde::{Deserialize, Serialize};
+
+/// Message from the client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Start a run; must be the first message
+    Start {
+        task: String,
+        /// Language to answer in (see `AgentRequest::locale`)
+        #[serde(default)]
+        locale: Option<String>,
+    },
+    /// Stop the run; the server answers `cancelled`
+    Cancel,
+    /// Answer a pending tool approval request
+    ApproveTool { call_id: String, approved: bool },
+}
+
+/// Message from the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Progress of the run, as also streamed over SSE
+    Event { event: Box<StreamEvent> },
+    /// The run was cancelled by the client
+    Cancelled,
+    /// The client sent something the server could not act on; the run continues
+    Error { message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_json() {
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type":"approve_tool","call_id":"call_1","approved":true}"#)
+                .unwrap();
+        assert_eq!(
+            message,
+            ClientMessage::ApproveTool {
+                call_id: "call_1".to_string(),
+                approved: true,
+            }
+        );
+
+        let event = ServerMessage::Event {
+            event: Box::new(StreamEvent::Delta {
+                content: "Hi".to_string(),
+            }),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "event", "event": {"type": "delta", "content": "Hi"}})
+        );
+    }
+}
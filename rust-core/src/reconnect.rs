@@ -0,0 +1,180 @@
+//! Reconnecting streams - resume generations cut off by a dropped connection
+
+use crate::{AgentError, AgentRequest, AgentResponse, LLMProvider};
+use async_trait::async_trait;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Provider wrapper that resumes interrupted streams
+///
+/// When `chat_stream` fails with `AgentError::NetworkError`, the request is
+/// sent again with the output streamed so far as an assistant prefill, and
+/// the model continues from there. Deltas are forwarded as they arrive, so
+/// the caller sees one uninterrupted stream; the returned result is the
+/// joined output with a warning noting each reconnect.
+///
+/// Limitations:
+/// - The cut can fall inside a word or token. The continuation is
+///   tokenized afresh and may not join seamlessly (a repeated or missing
+///   space, a re-started word).
+/// - Only APIs that continue a trailing assistant message (e.g. Anthropic
+///   Messages) resume in place. Others treat the prefill as an earlier turn
+///   and may repeat or rephrase it.
+/// - Tool calls are not resumed; a call being streamed when the connection
+///   dropped is requested again from scratch.
+/// - Sampling is not deterministic, so the resumed text is a plausible
+///   continuation, not the one the first connection would have produced.
+///
+/// `chat` is passed through unchanged.
+pub struct ReconnectingProvider {
+    inner: Box<dyn LLMProvider>,
+    max_reconnects: usize,
+}
+
+impl ReconnectingProvider {
+    /// Reconnect up to 3 times per call
+    pub fn new(inner: Box<dyn LLMProvider>) -> Self {
+        Self {
+            inner,
+            max_reconnects: 3,
+        }
+    }
+
+    pub fn with_max_reconnects(mut self, max_reconnects: usize) -> Self {
+        self.max_reconnects = max_reconnects;
+        self
+    }
+}
+
+#[async_trait]
+impl LLMProvider for ReconnectingProvider {
+    async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+        self.inner.chat(request).await
+    }
+
+    async fn chat_stream(
+        &self,
+        request: AgentRequest,
+        deltas: UnboundedSender<String>,
+    ) -> Result<AgentResponse, AgentError> {
+        let prefill = request.prefill.clone().unwrap_or_default();
+        let mut partial = String::new();
+        let mut warnings = Vec::new();
+
+        let mut reconnects = 0;
+        loop {
+            // Output streamed by earlier connections
+            let resumed = partial.len();
+            let mut request = request.clone();
+            if resumed > 0 {
+                request.prefill = Some(format!("{}{}", prefill, partial));
+            }
+
+            let (forward, mut received) = tokio::sync::mpsc::unbounded_channel::<String>();
+            let collect = async {
+                while let Some(content) = received.recv().await {
+                    partial.push_str(&content);
+                    let _ = deltas.send(content);
+                }
+            };
+            let (result, ()) = tokio::join!(self.inner.chat_stream(request, forward), collect);
+
+            match result {
+                Ok(mut response) => {
+                    response.result = format!("{}{}", &partial[..resumed], response.result);
+                    warnings.append(&mut response.warnings);
+                    response.warnings = warnings;
+                    return Ok(response);
+                }
+                Err(AgentError::NetworkError(e)) if reconnects < self.max_reconnects => {
+                    reconnects += 1;
+                    let chars = partial.chars().count();
+                    tracing::warn!(error = %e, chars, "stream dropped, reconnecting");
+                    warnings.push(format!(
+                        "stream dropped after {} characters and was resumed",
+                        chars
+                    ));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn validate(&self) -> Result<(), AgentError> {
+        self.inner.validate().await
+    }
+
+    async fn init(&self) -> Result<(), AgentError> {
+        self.inner.init().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Streams `Hello, ` and drops the connection, then continues the prefill
+    #[derive(Default)]
+    struct DroppingProvider {
+        prefills: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    #[async_trait]
+    impl LLMProvider for DroppingProvider {
+        async fn chat(&self, _request: AgentRequest) -> Result<AgentResponse, AgentError> {
+            unreachable!("only streaming is tested")
+        }
+
+        async fn chat_stream(
+            &self,
+            request: AgentRequest,
+            deltas: UnboundedSender<String>,
+        ) -> Result<AgentResponse, AgentError> {
+            self.prefills.lock().unwrap().push(request.prefill.clone());
+            if request.prefill.is_none() {
+                let _ = deltas.send("Hello, ".to_string());
+                let dropped = reqwest::Client::new().get("not a url").build().unwrap_err();
+                return Err(AgentError::NetworkError(dropped));
+            }
+            let _ = deltas.send("world".to_string());
+            Ok(AgentResponse {
+                result: "world".to_string(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_with_partial_output_as_prefill() {
+        let inner = DroppingProvider::default();
+        let prefills = inner.prefills.clone();
+        let provider = ReconnectingProvider::new(Box::new(inner));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let response = provider
+            .chat_stream(
+                AgentRequest {
+                    task: "Greet".to_string(),
+                    ..Default::default()
+                },
+                tx,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.result, "Hello, world");
+        assert_eq!(
+            response.warnings,
+            ["stream dropped after 7 characters and was resumed"]
+        );
+
+        let mut streamed = String::new();
+        while let Some(delta) = rx.recv().await {
+            streamed.push_str(&delta);
+        }
+        assert_eq!(streamed, "Hello, world");
+        assert_eq!(
+            *prefills.lock().unwrap(),
+            [None, Some("Hello, ".to_string())]
+        );
+    }
+}
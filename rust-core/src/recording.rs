@@ -0,0 +1,161 @@
+//! Conversation recording and replay
+
+use crate::{AgentError, AgentRequest, AgentResponse, LLMProvider, Message};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// One recorded task and the answer it received
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedTurn {
+    pub task: String,
+    pub result: String,
+}
+
+/// Conversation saved for later replay, stored as JSON
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordedConversation {
+    /// Model the conversation was recorded against, if known
+    #[serde(default)]
+    pub model: Option<String>,
+    pub turns: Vec<RecordedTurn>,
+}
+
+impl RecordedConversation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a completed turn
+    pub fn record(&mut self, task: impl Into<String>, response: &AgentResponse) {
+        self.turns.push(RecordedTurn {
+            task: task.into(),
+            result: response.result.clone(),
+        });
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let json = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| AgentError::ParseError(e.to_string()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AgentError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| AgentError::ParseError(e.to_string()))?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Recorded turns before `turn`, as alternating user/assistant messages
+    pub fn history(&self, turn: usize) -> Vec<Message> {
+        self.turns
+            .iter()
+            .take(turn)
+            .flat_map(|t| {
+                [
+                    Message::user(t.task.as_str()),
+                    Message::assistant(t.result.as_str()),
+                ]
+            })
+            .collect()
+    }
+
+    /// Re-run `turn` against `provider`, optionally overriding the model
+    ///
+    /// Earlier turns are sent as recorded history, so only the replayed
+    /// turn costs an API call.
+    pub async fn replay(
+        &self,
+        turn: usize,
+        provider: &dyn LLMProvider,
+        model: Option<String>,
+    ) -> Result<AgentResponse, AgentError> {
+        let recorded = self.turns.get(turn).ok_or_else(|| {
+            AgentError::ApiError(format!(
+                "turn {} out of range for a conversation of {} turns",
+                turn,
+                self.turns.len()
+            ))
+        })?;
+
+        let request = AgentRequest {
+            task: recorded.task.clone(),
+            model,
+            history: self.history(turn),
+            ..Default::default()
+        };
+        provider.chat(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Provider that records the requests it receives
+    #[derive(Default)]
+    struct CapturingProvider {
+        requests: Mutex<Vec<AgentRequest>>,
+    }
+
+    #[async_trait]
+    impl LLMProvider for CapturingProvider {
+        async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+            self.requests.lock().unwrap().push(request.clone());
+            Ok(AgentResponse {
+                result: format!("replayed: {}", request.task),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn conversation() -> RecordedConversation {
+        let mut conversation = RecordedConversation::new();
+        for (task, result) in [
+            ("Hi", "Hello!"),
+            ("Name a crate", "serde"),
+            ("Why?", "Popular"),
+        ] {
+            conversation.record(
+                task,
+                &AgentResponse {
+                    result: result.to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+        conversation
+    }
+
+    #[tokio::test]
+    async fn test_replay_sends_history_without_extra_calls() {
+        let provider = CapturingProvider::default();
+        let response = conversation()
+            .replay(2, &provider, Some("gpt-4o".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(response.result, "replayed: Why?");
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].model.as_deref(), Some("gpt-4o"));
+        assert_eq!(requests[0].history.len(), 4);
+        assert_eq!(requests[0].history[3], Message::assistant("serde"));
+
+        assert!(conversation().history(0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("agent-core-recording-{}.json", std::process::id()));
+        conversation().save(&path).unwrap();
+        let loaded = RecordedConversation::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.turns, conversation().turns);
+        let provider = CapturingProvider::default();
+        assert!(loaded.replay(3, &provider, None).await.is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Redaction - one place that decides which headers and body fields may
+//! be exposed
+
+use reqwest::header::HeaderMap;
+use std::collections::BTreeMap;
+
+/// Placeholder written in place of a redacted header value
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Header name fragments redacted by default
+const DEFAULT_DENIED: [&str; 3] = ["authorization", "api-key", "x-api-key"];
+
+/// Body paths of tool call arguments in the request and response formats of
+/// every model family, streamed or not (see `HeaderRedactor::with_tool_arguments`)
+pub const TOOL_ARGUMENT_PATHS: [&str; 7] = [
+    "messages.tool_calls.function.arguments",
+    "messages.content.input",
+    "contents.parts.functionCall.args",
+    "choices.message.tool_calls.function.arguments",
+    "choices.delta.tool_calls.function.arguments",
+    "content.input",
+    "candidates.content.parts.functionCall.args",
+];
+
+/// Decides which headers, and which fields of logged bodies, are redacted
+/// before they are logged or returned
+///
+/// A header is redacted when its name contains any denied fragment,
+/// compared case-insensitively, unless its full name is explicitly allowed.
+/// Use this helper wherever headers leave the process so the policy stays
+/// consistent.
+///
+/// Transports also log request and response bodies through `redact_body`,
+/// which masks nothing by default. Opt in per field with `with_body_path`,
+/// or mask every tool call argument with `with_tool_arguments`.
+#[derive(Debug, Clone)]
+pub struct HeaderRedactor {
+    denied: Vec<String>,
+    allowed: Vec<String>,
+    body_paths: Vec<String>,
+}
+
+impl HeaderRedactor {
+    /// Redactor with the default denylist (`authorization`, `api-key`,
+    /// `x-api-key`) and no body paths
+    pub fn new() -> Self {
+        Self {
+            denied: DEFAULT_DENIED.iter().map(|name| name.to_string()).collect(),
+            allowed: Vec::new(),
+            body_paths: Vec::new(),
+        }
+    }
+
+    /// Redactor with an empty denylist and no body paths
+    pub fn empty() -> Self {
+        Self {
+            denied: Vec::new(),
+            allowed: Vec::new(),
+            body_paths: Vec::new(),
+        }
+    }
+
+    /// Redact headers whose name contains `fragment`
+    pub fn with_denied(mut self, fragment: &str) -> Self {
+        self.denied.push(fragment.to_ascii_lowercase());
+        self
+    }
+
+    /// Always expose the header named `name`, even if it matches the denylist
+    pub fn with_allowed(mut self, name: &str) -> Self {
+        self.allowed.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Mask the body field at `path` (see `redact_json`)
+    pub fn with_body_path(mut self, path: &str) -> Self {
+        self.body_paths.push(path.to_string());
+        self
+    }
+
+    /// Mask tool call arguments in every family's bodies
+    /// (`TOOL_ARGUMENT_PATHS`)
+    pub fn with_tool_arguments(mut self) -> Self {
+        self.body_paths
+            .extend(TOOL_ARGUMENT_PATHS.iter().map(|path| path.to_string()));
+        self
+    }
+
+    pub fn is_redacted(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        if self.allowed.contains(&name) {
+            return false;
+        }
+        self.denied
+            .iter()
+            .any(|fragment| name.contains(fragment.as_str()))
+    }
+
+    /// Value to expose for a header, masking it if redacted
+    pub fn redact_value<'a>(&self, name: &str, value: &'a str) -> &'a str {
+        if self.is_redacted(name) {
+            REDACTED
+        } else {
+            value
+        }
+    }
+
+    /// Copy of `headers` safe to log or return, keyed by lowercase name
+    pub fn redact(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                (
+                    name.as_str().to_string(),
+                    self.redact_value(name.as_str(), &value).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// Copy of a request or response body safe to log
+    pub fn redact_body(&self, body: &serde_json::Value) -> serde_json::Value {
+        let paths: Vec<&str> = self.body_paths.iter().map(String::as_str).collect();
+        redact_json(body, &paths)
+    }
+}
+
+impl Default for HeaderRedactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Copy of `value` with the values at `paths` replaced by `REDACTED`
+///
+/// Paths are dot-separated object keys, e.g. `password` or `auth.token`;
+/// a path through an array applies to each element. Paths that do not
+/// exist are ignored.
+pub fn redact_json(value: &serde_json::Value, paths: &[&str]) -> serde_json::Value {
+    let mut value = value.clone();
+    for path in paths {
+        let keys: Vec<&str> = path.split('.').collect();
+        mask(&mut value, &keys);
+    }
+    value
+}
+
+fn mask(value: &mut serde_json::Value, keys: &[&str]) {
+    match value {
+        serde_json::Value::Array(items) => {
+            for item in items {
+                mask(item, keys);
+            }
+        }
+        serde_json::Value::Object(map) => match keys {
+            [] => {}
+            [key] => {
+                if let Some(value) = map.get_mut(*key) {
+                    *value = REDACTED.into();
+                }
+            }
+            [key, rest @ ..] => {
+                if let Some(value) = map.get_mut(*key) {
+                    mask(value, rest);
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_static("Bearer sk-secret"),
+        );
+        headers.insert("X-Api-Key", HeaderValue::from_static("secret"));
+        headers.insert("x-goog-api-key", HeaderValue::from_static("secret"));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        headers
+    }
+
+    #[test]
+    fn test_default_redaction() {
+        let redacted = HeaderRedactor::default().redact(&headers());
+        assert_eq!(redacted["authorization"], REDACTED);
+        assert_eq!(redacted["x-api-key"], REDACTED);
+        assert_eq!(redacted["x-goog-api-key"], REDACTED);
+        assert_eq!(redacted["content-type"], "application/json");
+    }
+
+    #[test]
+    fn test_allow_and_deny_lists() {
+        let redactor = HeaderRedactor::new()
+            .with_allowed("X-Goog-Api-Key")
+            .with_denied("Content-Type");
+        let redacted = redactor.redact(&headers());
+        assert_eq!(redacted["authorization"], REDACTED);
+        assert_eq!(redacted["x-goog-api-key"], "secret");
+        assert_eq!(redacted["content-type"], REDACTED);
+
+        assert!(!HeaderRedactor::empty().is_redacted("Authorization"));
+    }
+
+    #[test]
+    fn test_redact_json_paths() {
+        let arguments = serde_json::json!({
+            "query": "select 1",
+            "password": "hunter2",
+            "auth": {"token": "secret", "user": "admin"},
+            "hosts": [{"password": "a"}, {"password": "b"}],
+        });
+        let redacted = redact_json(
+            &arguments,
+            &["password", "auth.token", "hosts.password", "missing.key"],
+        );
+        assert_eq!(
+            redacted,
+            serde_json::json!({
+                "query": "select 1",
+                "password": REDACTED,
+                "auth": {"token": REDACTED, "user": "admin"},
+                "hosts": [{"password": REDACTED}, {"password": REDACTED}],
+            })
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Provider response bodies, deserialized into typed structs
+//!
+//! Only the fields the agent reads are declared; anything else a provider
+//! sends is ignored.
+
+use crate::{AgentError, AgentResponse, Thought, ToolCall};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// OpenAI chat completions response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIChatResponse {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub choices: Vec<OpenAIChoice>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIChoice {
+    pub message: OpenAIMessage,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIMessage {
+    /// `null` when the model only calls tools
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<OpenAIToolCall>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIToolCall {
+    pub id: String,
+    pub function: OpenAIFunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIFunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments
+    pub arguments: String,
+}
+
+impl TryFrom<OpenAIChatResponse> for AgentResponse {
+    type Error = AgentError;
+
+    fn try_from(response: OpenAIChatResponse) -> Result<Self, AgentError> {
+        let choice = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| AgentError::ParseError("choices: no choice returned".to_string()))?;
+
+        let tool_calls = choice
+            .message
+            .tool_calls
+            .into_iter()
+            .map(|call| ToolCall {
+                id: call.id,
+                name: call.function.name,
+                // Keep arguments the model failed to encode as a raw string
+                arguments: serde_json::from_str(&call.function.arguments)
+                    .unwrap_or(Value::String(call.function.arguments)),
+            })
+            .collect();
+        Ok(from_parts(
+            choice.message.content.unwrap_or_default(),
+            tool_calls,
+            response.usage,
+        ))
+    }
+}
+
+/// Anthropic messages response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicMessageResponse {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub content: Vec<AnthropicContentBlock>,
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    /// Block types the agent does not use, such as `thinking`
+    #[serde(other)]
+    Other,
+}
+
+impl From<AnthropicMessageResponse> for AgentResponse {
+    fn from(response: AnthropicMessageResponse) -> Self {
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        for block in response.content {
+            match block {
+                AnthropicContentBlock::Text { text: part } => text.push_str(&part),
+                AnthropicContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                    id,
+                    name,
+                    arguments: input,
+                }),
+                AnthropicContentBlock::Other => {}
+            }
+        }
+        from_parts(text, tool_calls, response.usage)
+    }
+}
+
+/// Gemini `generateContent` response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiGenerateResponse {
+    /// Empty when the prompt itself was blocked
+    #[serde(default)]
+    pub candidates: Vec<GeminiCandidate>,
+    #[serde(default)]
+    pub usage_metadata: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiCandidate {
+    /// Missing when the answer was blocked by the safety settings
+    #[serde(default)]
+    pub content: Option<GeminiContent>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiContent {
+    #[serde(default)]
+    pub parts: Vec<GeminiPart>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPart {
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub function_call: Option<GeminiFunctionCall>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiFunctionCall {
+    /// Only newer models number their calls
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub args: Value,
+}
+
+impl TryFrom<GeminiGenerateResponse> for AgentResponse {
+    type Error = AgentError;
+
+    fn try_from(response: GeminiGenerateResponse) -> Result<Self, AgentError> {
+        let candidate = response.candidates.into_iter().next().ok_or_else(|| {
+            AgentError::ParseError("candidates: no candidate returned".to_string())
+        })?;
+        let Some(content) = candidate.content else {
+            return Err(AgentError::ApiError(format!(
+                "answer withheld (finish reason {})",
+                candidate.finish_reason.as_deref().unwrap_or("unknown")
+            )));
+        };
+
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        for part in content.parts {
+            if let Some(part) = part.text {
+                text.push_str(&part);
+            }
+            if let Some(call) = part.function_call {
+                tool_calls.push(ToolCall {
+                    id: call
+                        .id
+                        .unwrap_or_else(|| format!("call_{}", tool_calls.len() + 1)),
+                    name: call.name,
+                    arguments: call.args,
+                });
+            }
+        }
+        Ok(from_parts(text, tool_calls, response.usage_metadata))
+    }
+}
+
+/// Tokens a model call consumed, as reported by the provider
+///
+/// Reads OpenAI (`prompt_tokens`, `completion_tokens`), Anthropic
+/// (`input_tokens`, `output_tokens`) and Gemini (`promptTokenCount`,
+/// `candidatesTokenCount`) field names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default, alias = "prompt_tokens", alias = "promptTokenCount")]
+    pub input_tokens: u64,
+    #[serde(default, alias = "completion_tokens", alias = "candidatesTokenCount")]
+    pub output_tokens: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    pub fn add(&mut self, other: Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// Deserialize `body`, naming the path of the offending field on failure
+pub(crate) fn decode<T: DeserializeOwned>(body: &Value) -> Result<T, AgentError> {
+    serde_path_to_error::deserialize(body).map_err(|e| AgentError::ParseError(e.to_string()))
+}
+
+/// Response carrying the generated text and requested tool calls
+///
+/// Text that accompanies tool calls is the model's reasoning, so it is also
+/// recorded as a thought.
+pub(crate) fn from_parts(
+    text: String,
+    tool_calls: Vec<ToolCall>,
+    usage: Option<Usage>,
+) -> AgentResponse {
+    let thoughts = if !tool_calls.is_empty() && !text.is_empty() {
+        vec![Thought {
+            thought_type: "thought".to_string(),
+            content: text.clone(),
+        }]
+    } else {
+        Vec::new()
+    };
+
+    AgentResponse {
+        result: text,
+        thoughts,
+        tool_calls,
+        usage,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_missing_field_is_named() {
+        let error = decode::<OpenAIChatResponse>(&json!({
+            "choices": [{"message": {"tool_calls": [{"id": "call_1", "function": {"name": "search"}}]}}],
+        }))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Parse error: choices[0].message.tool_calls[0].function: missing field `arguments`"
+        );
+
+        let response: AnthropicMessageResponse = decode(&json!({
+            "content": [{"type": "thinking", "thinking": "..."}, {"type": "text", "text": "Hi"}],
+            "usage": {"input_tokens": 3},
+        }))
+        .unwrap();
+        let response = AgentResponse::from(response);
+        assert_eq!(response.result, "Hi");
+        assert_eq!(
+            response.usage,
+            Some(Usage {
+                input_tokens: 3,
+                output_tokens: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Provider router - pick one of several named providers per request
+
+use crate::{AgentError, AgentRequest, AgentResponse, LLMProvider};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Provider that forwards each request to the provider named by
+/// `AgentRequest.provider`
+///
+/// Requests without a provider name go to the default provider, the one
+/// passed to `new`; unknown names fail with `AgentError::Validation`.
+/// `validate` and `init` run on every provider.
+pub struct ProviderRouter {
+    default: String,
+    providers: BTreeMap<String, Box<dyn LLMProvider>>,
+}
+
+impl ProviderRouter {
+    pub fn new(name: impl Into<String>, provider: Box<dyn LLMProvider>) -> Self {
+        let default = name.into();
+        let mut providers = BTreeMap::new();
+        providers.insert(default.clone(), provider);
+        Self { default, providers }
+    }
+
+    /// Register `provider` under `name`, replacing any provider of that name
+    pub fn with_provider(
+        mut self,
+        name: impl Into<String>,
+        provider: Box<dyn LLMProvider>,
+    ) -> Self {
+        self.providers.insert(name.into(), provider);
+        self
+    }
+
+    /// Registered provider names, in order
+    pub fn names(&self) -> Vec<String> {
+        self.providers.keys().cloned().collect()
+    }
+
+    fn select(&self, request: &AgentRequest) -> Result<&dyn LLMProvider, AgentError> {
+        let name = request.provider.as_deref().unwrap_or(&self.default);
+        self.providers
+            .get(name)
+            .map(|provider| provider.as_ref())
+            .ok_or_else(|| AgentError::Validation(format!("unknown provider: {}", name)))
+    }
+}
+
+#[async_trait]
+impl LLMProvider for ProviderRouter {
+    async fn chat(&self, request: AgentRequest) -> Result<AgentResponse, AgentError> {
+        self.select(&request)?.chat(request).await
+    }
+
+    async fn chat_stream(
+        &self,
+        request: AgentRequest,
+        deltas: UnboundedSender<String>,
+    ) -> Result<AgentResponse, AgentError> {
+        self.select(&request)?.chat_stream(request, deltas).await
+    }
+
+    async fn validate(&self) -> Result<(), AgentError> {
+        for provider in self.providers.values() {
+            provider.validate().await?;
+        }
+        Ok(())
+    }
+
+    async fn init(&self) -> Result<(), AgentError> {
+        for provider in self.providers.values() {
+            provider.init().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockProvider;
+
+    #[tokio::test]
+    async fn test_routes_by_provider_name() {
+        let named = |result: &str| {
+            Box::new(MockProvider::new().with_response(AgentResponse {
+                result: result.to_string(),
+                ..Default::default()
+            }))
+        };
+        let router = ProviderRouter::new("openai", named("from openai"))
+            .with_provider("anthropic", named("from anthropic"));
+        assert_eq!(router.names(), ["anthropic", "openai"]);
+
+        let request = |provider: Option<&str>| AgentRequest {
+            task: "Hello".to_string(),
+            provider: provider.map(str::to_string),
+            ..Default::default()
+        };
+        let response = router.chat(request(Some("anthropic"))).await.unwrap();
+        assert_eq!(response.result, "from anthropic");
+        assert_eq!(
+            router.chat(request(None)).await.unwrap().result,
+            "from openai"
+        );
+        assert!(matches!(
+            router.chat(request(Some("ollama"))).await,
+            Err(AgentError::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,79 @@
+//! Run state - checkpoint an agent run and resume it later
+
+use crate::{AgentError, AgentResponse, Message, Thought, TraceNode, Usage};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Everything needed to continue an agent run, stored as JSON
+///
+/// Produced when a run pauses (see `RunOptions::pause_after`) and passed
+/// to `ReActAgent::resume`, possibly in another process.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunState {
+    pub task: String,
+    /// Model steps completed so far
+    pub step: usize,
+    /// Actions and observations as text, appended to the task on every step
+    ///
+    /// Only checkpoints written before `turns` existed have one; runs now
+    /// record their tool calls and results in `turns`.
+    #[serde(default)]
+    pub scratchpad: String,
+    /// Tool calls and their results so far, sent after the task
+    #[serde(default)]
+    pub turns: Vec<Message>,
+    pub thoughts: Vec<Thought>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// Most recent model output, returned if the run times out softly
+    #[serde(default)]
+    pub last_result: String,
+    /// Retrieved chunks sent with every step
+    #[serde(default)]
+    pub context: Vec<String>,
+    #[serde(default)]
+    pub retrieved_chunks: usize,
+    /// Retrieved chunks that did not fit the context budget
+    #[serde(default)]
+    pub dropped_chunks: Vec<String>,
+    /// The relevance gate skipped retrieval
+    #[serde(default)]
+    pub retrieval_skipped: bool,
+    /// Trace tree so far, kept when the agent builds trace trees
+    #[serde(default)]
+    pub trace: Vec<TraceNode>,
+    /// Tokens reported by the model calls so far, counted against the
+    /// agent's token budget
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+impl RunState {
+    /// State of a run that has not started yet
+    pub fn new(task: impl Into<String>) -> Self {
+        Self {
+            task: task.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let json = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| AgentError::ParseError(e.to_string()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AgentError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| AgentError::ParseError(e.to_string()))?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+/// How a resumable run ended
+#[derive(Debug, Clone)]
+pub enum RunOutcome {
+    Finished(AgentResponse),
+    /// The run reached its pause point and can be resumed from this state
+    Paused(RunState),
+}
@@ -0,0 +1,314 @@
+//! SQLite vector store - documents and embeddings persisted row by row
+
+use crate::embedding::cosine_similarity;
+use crate::{content_id, AgentError, CancellationToken, Embedder, HashEmbedder, VectorStore};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS documents (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    id TEXT UNIQUE,
+    text TEXT NOT NULL,
+    metadata TEXT NOT NULL,
+    embedding BLOB NOT NULL
+)";
+
+/// Vector store kept in a SQLite database
+///
+/// Every `add`, `upsert` and `delete` writes only the rows it touches, in
+/// its own transaction, so the file is consistent after each call and is
+/// never rewritten whole. `add_batch` writes its batch in one transaction.
+/// The store holds one connection behind a mutex, which serializes
+/// concurrent writers; documents are embedded before the lock is taken.
+/// Searches scan every row and score by cosine similarity, equal scores
+/// ranking in insertion order.
+pub struct SqliteVectorStore {
+    connection: Mutex<Connection>,
+    embedder: Box<dyn Embedder>,
+}
+
+impl SqliteVectorStore {
+    /// Open or create the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        Self::with_connection(Connection::open(path).map_err(storage_error)?)
+    }
+
+    /// Store in a private in-memory database, gone when the store is dropped
+    pub fn open_in_memory() -> Result<Self, AgentError> {
+        Self::with_connection(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, AgentError> {
+        connection.execute(SCHEMA, []).map_err(storage_error)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            embedder: Box::new(HashEmbedder::default()),
+        })
+    }
+
+    /// Embed with `embedder` instead of the default `HashEmbedder`
+    ///
+    /// Use the embedder the stored rows were embedded with.
+    pub fn with_embedder(mut self, embedder: Box<dyn Embedder>) -> Self {
+        self.embedder = embedder;
+        self
+    }
+
+    /// Add `text`, or replace the document with the same content id,
+    /// returning the id (see `content_id`)
+    pub fn upsert(&self, text: String, metadata: serde_json::Value) -> Result<String, AgentError> {
+        let id = content_id(None, &text);
+        let embedding = self.embed(&text)?;
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(storage_error)?;
+        transaction
+            .execute(
+                "INSERT INTO documents (id, text, metadata, embedding) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (id) DO UPDATE SET
+                     text = excluded.text,
+                     metadata = excluded.metadata,
+                     embedding = excluded.embedding",
+                params![id, text, metadata.to_string(), to_blob(&embedding)],
+            )
+            .map_err(storage_error)?;
+        transaction.commit().map_err(storage_error)?;
+        Ok(id)
+    }
+
+    /// Remove the document `upsert` stored under `id`, returning whether
+    /// there was one
+    pub fn delete(&self, id: &str) -> Result<bool, AgentError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(storage_error)?;
+        let deleted = transaction
+            .execute("DELETE FROM documents WHERE id = ?1", params![id])
+            .map_err(storage_error)?;
+        transaction.commit().map_err(storage_error)?;
+        Ok(deleted > 0)
+    }
+
+    /// Metadata of the document stored under `id`
+    pub fn metadata(&self, id: &str) -> Result<Option<serde_json::Value>, AgentError> {
+        let connection = self.connection.lock().unwrap();
+        let metadata: Option<String> = connection
+            .query_row(
+                "SELECT metadata FROM documents WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_error)?;
+        metadata.map(|json| parse_metadata(&json)).transpose()
+    }
+
+    /// Number of stored documents
+    pub fn len(&self) -> Result<usize, AgentError> {
+        let connection = self.connection.lock().unwrap();
+        let count: i64 = connection
+            .query_row("SELECT COUNT(*) FROM documents", [], |row| row.get(0))
+            .map_err(storage_error)?;
+        Ok(count as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, AgentError> {
+        Ok(self.len()? == 0)
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, AgentError> {
+        let embedding = self.embedder.embed(text)?;
+        self.check_dimensions(&embedding)?;
+        Ok(embedding)
+    }
+
+    fn check_dimensions(&self, embedding: &[f32]) -> Result<(), AgentError> {
+        if embedding.len() != self.embedder.dimensions() {
+            return Err(AgentError::ApiError(format!(
+                "document embedding has {} dimensions, expected {}",
+                embedding.len(),
+                self.embedder.dimensions()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Best `limit` rows for `query` as text, score and embedding
+    fn rank(&self, query: &str, limit: usize) -> Result<Vec<(String, f32, Vec<f32>)>, AgentError> {
+        let query = self.embedder.embed(query)?;
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT text, embedding FROM documents ORDER BY seq")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(storage_error)?;
+        let mut hits = Vec::new();
+        for row in rows {
+            let (text, blob) = row.map_err(storage_error)?;
+            let embedding = from_blob(&blob);
+            hits.push((text, cosine_similarity(&query, &embedding), embedding));
+        }
+        // Stable, so equal scores keep insertion order
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+}
+
+impl VectorStore for SqliteVectorStore {
+    fn add(&self, text: String, metadata: serde_json::Value) -> Result<(), AgentError> {
+        let embedding = self.embed(&text)?;
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(storage_error)?;
+        transaction
+            .execute(
+                "INSERT INTO documents (text, metadata, embedding) VALUES (?1, ?2, ?3)",
+                params![text, metadata.to_string(), to_blob(&embedding)],
+            )
+            .map_err(storage_error)?;
+        transaction.commit().map_err(storage_error)
+    }
+
+    fn search(&self, query: String, limit: usize) -> Result<Vec<(String, f32)>, AgentError> {
+        let hits = self.rank(&query, limit)?;
+        Ok(hits
+            .into_iter()
+            .map(|(text, score, _)| (text, score))
+            .collect())
+    }
+
+    fn search_with_embeddings(
+        &self,
+        query: String,
+        limit: usize,
+    ) -> Result<Vec<(String, f32, Vec<f32>)>, AgentError> {
+        self.rank(&query, limit)
+    }
+
+    /// Embeds the batch with `Embedder::embed_batch`, then writes it in one
+    /// transaction: all of it is added or none
+    fn add_batch(
+        &self,
+        documents: Vec<(String, serde_json::Value)>,
+        cancel: &CancellationToken,
+    ) -> Result<usize, AgentError> {
+        let texts: Vec<&str> = documents.iter().map(|(text, _)| text.as_str()).collect();
+        let embeddings = self.embedder.embed_batch(&texts, cancel)?;
+        for embedding in &embeddings {
+            self.check_dimensions(embedding)?;
+        }
+        cancel.check(|| format!("added 0 of {} documents", documents.len()))?;
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(storage_error)?;
+        {
+            let mut insert = transaction
+                .prepare("INSERT INTO documents (text, metadata, embedding) VALUES (?1, ?2, ?3)")
+                .map_err(storage_error)?;
+            for ((text, metadata), embedding) in documents.iter().zip(&embeddings) {
+                insert
+                    .execute(params![text, metadata.to_string(), to_blob(embedding)])
+                    .map_err(storage_error)?;
+            }
+        }
+        transaction.commit().map_err(storage_error)?;
+        Ok(documents.len())
+    }
+}
+
+/// SQLite failures are reported as I/O errors
+fn storage_error(error: rusqlite::Error) -> AgentError {
+    AgentError::IoError(std::io::Error::other(error))
+}
+
+fn parse_metadata(json: &str) -> Result<serde_json::Value, AgentError> {
+    serde_json::from_str(json)
+        .map_err(|e| AgentError::ParseError(format!("stored metadata: {}", e)))
+}
+
+/// Embedding as little-endian `f32`s
+fn to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_writes_persist_row_by_row() {
+        let path = std::env::temp_dir().join(format!("agent-sqlite-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = SqliteVectorStore::open(&path).unwrap();
+        store
+            .add("rust ownership rules".to_string(), serde_json::json!({}))
+            .unwrap();
+        let id = store
+            .upsert("rust borrowing".to_string(), serde_json::json!({"v": 1}))
+            .unwrap();
+        let again = store
+            .upsert("rust borrowing".to_string(), serde_json::json!({"v": 2}))
+            .unwrap();
+        assert_eq!(id, again);
+        assert_eq!(store.len().unwrap(), 2);
+        drop(store);
+
+        // Every write was committed as it was made
+        let store = SqliteVectorStore::open(&path).unwrap();
+        assert_eq!(
+            store.metadata(&id).unwrap(),
+            Some(serde_json::json!({"v": 2}))
+        );
+        let hits = store.search("rust ownership".to_string(), 1).unwrap();
+        assert_eq!(hits[0].0, "rust ownership rules");
+        assert!(store.delete(&id).unwrap());
+        assert!(!store.delete(&id).unwrap());
+        assert_eq!(store.len().unwrap(), 1);
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_writers_are_serialized() {
+        let store = Arc::new(SqliteVectorStore::open_in_memory().unwrap());
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        let text = format!("document {} from writer {}", i, writer);
+                        store.upsert(text, serde_json::json!({})).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(store.len().unwrap(), 100);
+
+        let cancel = CancellationToken::new();
+        let batch = vec![
+            ("alpha".to_string(), serde_json::json!({})),
+            ("beta".to_string(), serde_json::json!({})),
+        ];
+        assert_eq!(store.add_batch(batch.clone(), &cancel).unwrap(), 2);
+        cancel.cancel();
+        assert!(matches!(
+            store.add_batch(batch, &cancel),
+            Err(AgentError::Cancelled(_))
+        ));
+        assert_eq!(store.len().unwrap(), 102);
+    }
+}
@@ -0,0 +1,180 @@
+//! Server-sent event streams of OpenAI-style chat completions
+
+use crate::responses::from_parts;
+use crate::{AgentError, AgentResponse, ToolCall, Usage};
+use serde_json::Value;
+
+/// Incremental decoder of a `stream: true` chat completions response
+///
+/// Feed the body with `push` as it arrives and call `finish` once the
+/// connection closes. The `[DONE]` sentinel is optional: many compatible
+/// servers just close the connection, which ends the stream normally as
+/// long as it closed between events. A close in the middle of an event is
+/// an error, as is a stream that carried no data at all.
+#[derive(Debug, Default)]
+pub struct ChatStreamDecoder {
+    /// Text after the last complete line
+    pending: String,
+    content: String,
+    /// Tool calls by stream index, their arguments still JSON text
+    tool_calls: Vec<(String, String, String)>,
+    usage: Option<Usage>,
+    events: usize,
+    done: bool,
+}
+
+impl ChatStreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode the complete lines in `text`, returning their content deltas
+    pub fn push(&mut self, text: &str) -> Result<Vec<String>, AgentError> {
+        self.pending.push_str(text);
+        let mut deltas = Vec::new();
+        while let Some(end) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=end).collect();
+            if let Some(delta) = self.line(line.trim_end_matches(['\n', '\r']))? {
+                deltas.push(delta);
+            }
+        }
+        Ok(deltas)
+    }
+
+    /// The response the stream described, once the connection has closed
+    ///
+    /// A final line without its newline is accepted if it is a whole event.
+    pub fn finish(mut self) -> Result<AgentResponse, AgentError> {
+        let rest = std::mem::take(&mut self.pending);
+        if !self.done && !rest.trim().is_empty() {
+            self.line(rest.trim_end()).map_err(|_| {
+                AgentError::ApiError(format!(
+                    "stream ended mid-event after {} events",
+                    self.events
+                ))
+            })?;
+        }
+        if self.events == 0 {
+            return Err(AgentError::ApiError(
+                "stream ended before any data".to_string(),
+            ));
+        }
+
+        let tool_calls = self
+            .tool_calls
+            .into_iter()
+            .map(|(id, name, arguments)| ToolCall {
+                id,
+                name,
+                // Keep arguments the model failed to encode as a raw string
+                arguments: serde_json::from_str(&arguments).unwrap_or(Value::String(arguments)),
+            })
+            .collect();
+        Ok(from_parts(self.content, tool_calls, self.usage))
+    }
+
+    /// Handle one line of the stream, returning its content delta if any
+    fn line(&mut self, line: &str) -> Result<Option<String>, AgentError> {
+        // Comments, other fields and event separators carry no data
+        let Some(data) = line.strip_prefix("data:") else {
+            return Ok(None);
+        };
+        let data = data.trim_start();
+        if self.done {
+            return Ok(None);
+        }
+        if data == "[DONE]" {
+            self.done = true;
+            return Ok(None);
+        }
+        let chunk: Value = serde_json::from_str(data)
+            .map_err(|e| AgentError::ParseError(format!("stream event: {}", e)))?;
+        self.events += 1;
+        if let Some(usage) = chunk.get("usage").filter(|usage| !usage.is_null()) {
+            self.usage = serde_json::from_value(usage.clone()).ok();
+        }
+
+        let delta = &chunk["choices"][0]["delta"];
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let index = call["index"].as_u64().unwrap_or(0) as usize;
+            if self.tool_calls.len() <= index {
+                self.tool_calls.resize(index + 1, Default::default());
+            }
+            let (id, name, arguments) = &mut self.tool_calls[index];
+            if let Some(part) = call["id"].as_str() {
+                id.push_str(part);
+            }
+            if let Some(part) = call["function"]["name"].as_str() {
+                name.push_str(part);
+            }
+            if let Some(part) = call["function"]["arguments"].as_str() {
+                arguments.push_str(part);
+            }
+        }
+        match delta["content"].as_str() {
+            Some(content) if !content.is_empty() => {
+                self.content.push_str(content);
+                Ok(Some(content.to_string()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(content: &str) -> String {
+        format!(
+            "data: {}\n\n",
+            serde_json::json!({"choices": [{"delta": {"content": content}}]})
+        )
+    }
+
+    #[test]
+    fn test_stream_without_done_ends_cleanly() {
+        let mut decoder = ChatStreamDecoder::new();
+        let body = format!("{}{}", event("Hel"), event("lo"));
+        // Split inside an event, as network reads would
+        let (first, second) = body.split_at(10);
+        assert!(decoder.push(first).unwrap().is_empty());
+        assert_eq!(decoder.push(second).unwrap(), ["Hel", "lo"]);
+        assert_eq!(decoder.finish().unwrap().result, "Hello");
+
+        let mut decoder = ChatStreamDecoder::new();
+        decoder
+            .push(&format!("{}data: [DONE]\n\n", event("Hi")))
+            .unwrap();
+        assert_eq!(decoder.finish().unwrap().result, "Hi");
+    }
+
+    #[test]
+    fn test_stream_cut_mid_event_fails() {
+        let mut decoder = ChatStreamDecoder::new();
+        let body = format!("{}{}", event("Hel"), event("lo"));
+        decoder.push(&body[..body.len() - 8]).unwrap();
+        assert!(matches!(decoder.finish(), Err(AgentError::ApiError(_))));
+
+        assert!(ChatStreamDecoder::new().finish().is_err());
+    }
+
+    #[test]
+    fn test_stream_tool_calls_are_assembled() {
+        let mut decoder = ChatStreamDecoder::new();
+        for delta in [
+            serde_json::json!({"tool_calls": [{"index": 0, "id": "call_1", "function": {"name": "search", "arguments": ""}}]}),
+            serde_json::json!({"tool_calls": [{"index": 0, "function": {"arguments": "{\"query\":"}}]}),
+            serde_json::json!({"tool_calls": [{"index": 0, "function": {"arguments": "\"rust\"}"}}]}),
+        ] {
+            let chunk = serde_json::json!({"choices": [{"delta": delta}]});
+            decoder.push(&format!("data: {}\n\n", chunk)).unwrap();
+        }
+        let response = decoder.finish().unwrap();
+        assert_eq!(response.tool_calls[0].id, "call_1");
+        assert_eq!(
+            response.tool_calls[0].arguments,
+            serde_json::json!({"query": "rust"})
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Streaming events emitted while an agent run is in progress
+
+use crate::{AgentEvent, AgentResponse, Thought, ToolCall};
+use serde::{Deserialize, Serialize};
+
+/// Event emitted by `ReActAgent::execute_stream`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// The run was accepted; always the first event, sent before the
+    /// provider is called
+    ///
+    /// `run_id` matches the run's `AgentEvent`s and `InflightRun`.
+    Accepted { run_id: u64 },
+    /// A reasoning step, action or observation
+    Thought(Thought),
+    /// A chunk of generated content
+    Delta { content: String },
+    /// JSON Merge Patch (RFC 7396) to the object the answer describes so far
+    ///
+    /// Only sent by agents built with `ReActAgent::with_json_patches`.
+    /// Applying every patch in order to `{}` rebuilds the object.
+    Patch { patch: serde_json::Value },
+    /// Status reported by a tool while it is still running
+    ToolProgress { tool: String, message: String },
+    /// The run is waiting for the approver to allow or deny `call`
+    ApprovalRequested { call: ToolCall },
+    /// Lifecycle event of the run (step, tool call, finish), as also sent
+    /// to the agent's `EventSink`
+    ///
+    /// Only sent for runs with `RunOptions::lifecycle_events`.
+    Lifecycle { event: AgentEvent },
+    /// The run finished; `Done` or `Error` is always the last event
+    Done(AgentResponse),
+    /// The run failed
+    Error { message: String },
+}
+
+impl StreamEvent {
+    /// Event name, matching the serialized `type` tag
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamEvent::Accepted { .. } => "accepted",
+            StreamEvent::Thought(_) => "thought",
+            StreamEvent::Delta { .. } => "delta",
+            StreamEvent::Patch { .. } => "patch",
+            StreamEvent::ToolProgress { .. } => "tool_progress",
+            StreamEvent::ApprovalRequested { .. } => "approval_requested",
+            StreamEvent::Lifecycle { .. } => "lifecycle",
+            StreamEvent::Done(_) => "done",
+            StreamEvent::Error { .. } => "error",
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Swappable vector store - replace the live index without downtime
+
+use crate::{AgentError, CancellationToken, VectorStore};
+use std::sync::{Arc, RwLock};
+
+/// Vector store whose backing store can be replaced while it serves reads
+///
+/// Clones share the active store, so keep one clone to `swap` and give
+/// another to the agent. Build the replacement (e.g. a reindexed
+/// `MemoryVectorStore`) in the background, then swap it in: searches that
+/// already started finish on the old store and every later one sees the
+/// new store, so none see a half-built index. The lock is held only to
+/// clone or replace the active `Arc`, never during a search. Writes made
+/// to the old store during a rebuild are not carried over.
+#[derive(Clone)]
+pub struct SwappableVectorStore {
+    active: Arc<RwLock<Arc<dyn VectorStore>>>,
+}
+
+impl SwappableVectorStore {
+    pub fn new(store: Box<dyn VectorStore>) -> Self {
+        Self {
+            active: Arc::new(RwLock::new(Arc::from(store))),
+        }
+    }
+
+    /// Make `store` the active store, returning the one it replaced
+    pub fn swap(&self, store: Box<dyn VectorStore>) -> Arc<dyn VectorStore> {
+        std::mem::replace(&mut *self.active.write().unwrap(), Arc::from(store))
+    }
+
+    /// Store serving requests right now
+    pub fn current(&self) -> Arc<dyn VectorStore> {
+        self.active.read().unwrap().clone()
+    }
+}
+
+impl VectorStore for SwappableVectorStore {
+    fn add(&self, text: String, metadata: serde_json::Value) -> Result<(), AgentError> {
+        self.current().add(text, metadata)
+    }
+
+    fn search(&self, query: String, limit: usize) -> Result<Vec<(String, f32)>, AgentError> {
+        self.current().search(query, limit)
+    }
+
+    fn search_page(
+        &self,
+        query: String,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<(String, f32)>, AgentError> {
+        self.current().search_page(query, offset, limit)
+    }
+
+    fn search_with_embeddings(
+        &self,
+        query: String,
+        limit: usize,
+    ) -> Result<Vec<(String, f32, Vec<f32>)>, AgentError> {
+        self.current().search_with_embeddings(query, limit)
+    }
+
+    fn add_batch(
+        &self,
+        documents: Vec<(String, serde_json::Value)>,
+        cancel: &CancellationToken,
+    ) -> Result<usize, AgentError> {
+        self.current().add_batch(documents, cancel)
+    }
+
+    fn document_id(&self, text: &str) -> String {
+        self.current().document_id(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryVectorStore;
+
+    #[test]
+    fn test_swap_replaces_store_for_later_searches() {
+        let old = MemoryVectorStore::new();
+        old.add("stale document".to_string(), serde_json::json!({}))
+            .unwrap();
+        let store = SwappableVectorStore::new(Box::new(old));
+        let served = store.clone();
+
+        let rebuilt = MemoryVectorStore::new();
+        rebuilt
+            .add("fresh document".to_string(), serde_json::json!({}))
+            .unwrap();
+        let replaced = store.swap(Box::new(rebuilt));
+
+        let hits = served.search("document".to_string(), 5).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0, "fresh document");
+        assert_eq!(
+            replaced.search("document".to_string(), 5).unwrap()[0].0,
+            "stale document"
+        );
+    }
+}
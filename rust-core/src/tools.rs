@@ -0,0 +1,124 @@
+//! Tools the ReAct agent can call
+
+use crate::{AgentError, StreamEvent, TraceNode};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Tool description sent to the model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments
+    pub parameters: serde_json::Value,
+}
+
+/// Tool invocation requested by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// Outcome of a `ToolCall`, sent back to the model
+///
+/// Provider-neutral: each `ModelFamily` turns it into its API's shape.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResult {
+    /// `ToolCall::id` of the call this answers
+    pub call_id: String,
+    pub name: String,
+    pub content: String,
+    /// The tool failed and `content` describes the failure
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+/// Channel a running tool can report progress through
+///
+/// Reports are forwarded as `StreamEvent::ToolProgress` when the run is
+/// streaming and silently dropped otherwise, so tools can always report.
+#[derive(Debug, Clone)]
+pub struct ToolProgressSender {
+    tool: String,
+    events: Option<UnboundedSender<StreamEvent>>,
+    children: Arc<Mutex<Vec<TraceNode>>>,
+}
+
+impl ToolProgressSender {
+    pub(crate) fn new(tool: &str, events: Option<UnboundedSender<StreamEvent>>) -> Self {
+        Self {
+            tool: tool.to_string(),
+            events,
+            children: Arc::default(),
+        }
+    }
+
+    /// Progress sender that discards every report
+    pub fn disabled(tool: &str) -> Self {
+        Self::new(tool, None)
+    }
+
+    pub fn report(&self, message: impl Into<String>) {
+        if let Some(events) = &self.events {
+            let _ = events.send(StreamEvent::ToolProgress {
+                tool: self.tool.clone(),
+                message: message.into(),
+            });
+        }
+    }
+
+    /// Nest `trace` under this call in the caller's trace tree
+    ///
+    /// For tools that run a sub-agent: pass its `AgentResponse.trace`. May
+    /// be called more than once; ignored unless the calling agent builds
+    /// trace trees.
+    pub fn attach_trace(&self, trace: Vec<TraceNode>) {
+        self.children.lock().unwrap().extend(trace);
+    }
+
+    pub(crate) fn take_trace(&self) -> Vec<TraceNode> {
+        std::mem::take(&mut *self.children.lock().unwrap())
+    }
+}
+
+/// Tool trait
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({"type": "object"})
+    }
+
+    /// Argument paths masked wherever the agent logs or reports the call,
+    /// e.g. `password`
+    ///
+    /// Dot-separated object keys (see `redact_json`). Override this for
+    /// tools that take secrets; the default redacts nothing. The tool itself
+    /// still receives the real arguments. Provider bodies logged by the
+    /// transport are masked separately, see `HeaderRedactor`.
+    fn sensitive_arguments(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Run the tool; long-running tools may report status through `progress`
+    async fn call(
+        &self,
+        arguments: serde_json::Value,
+        progress: &ToolProgressSender,
+    ) -> Result<String, AgentError>;
+
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: self.name().to_string(),
+            description: self.description().to_string(),
+            parameters: self.parameters(),
+        }
+    }
+}
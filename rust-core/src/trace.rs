@@ -0,0 +1,27 @@
+//! Trace trees - a run's steps with the runs of any sub-agents nested inside
+
+use serde::{Deserialize, Serialize};
+
+/// One node of `AgentResponse.trace`
+///
+/// Unlike the flat `thoughts` list, a tool call node holds the traces its
+/// tool attached through `ToolProgressSender::attach_trace`, so agents
+/// called from tools show up as subtrees.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceNode {
+    /// Reasoning returned by a model step
+    Thought {
+        thought_type: String,
+        content: String,
+    },
+    /// A tool call and what it returned to the model
+    ToolCall {
+        name: String,
+        /// With the tool's sensitive arguments redacted
+        arguments: serde_json::Value,
+        observation: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        children: Vec<TraceNode>,
+    },
+}
@@ -0,0 +1,507 @@
+//! HTTP transport - the seam between providers and the network
+
+use crate::{AgentError, HeaderRedactor};
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// Outbound HTTP request built by a provider
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Raw HTTP response handed back to the provider
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Response with `status` and a JSON body
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            reqwest::header::HeaderValue::from_static("application/json"),
+        );
+        Self {
+            status,
+            headers,
+            body: body.to_string().into_bytes(),
+        }
+    }
+}
+
+/// Transport trait
+///
+/// Providers send every request through a transport instead of calling
+/// reqwest directly, so their request building and response handling can
+/// be tested in-process with `MockTransport`.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AgentError>;
+
+    /// Policy for the headers and bodies logged around each request
+    fn redactor(&self) -> &HeaderRedactor {
+        &DEFAULT_REDACTOR
+    }
+}
+
+static DEFAULT_REDACTOR: LazyLock<HeaderRedactor> = LazyLock::new(HeaderRedactor::default);
+
+/// Default transport backed by a shared `reqwest::Client`
+///
+/// Compressed responses (gzip, deflate, brotli) are decompressed
+/// transparently: the client advertises them in `accept-encoding` and
+/// providers see the decoded body. Turn this off with
+/// `TransportConfig::decompression`.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+    redactor: HeaderRedactor,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            redactor: HeaderRedactor::default(),
+        }
+    }
+
+    /// Log requests through `redactor` instead of the default policy
+    pub fn with_redactor(mut self, redactor: HeaderRedactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Transport whose client is tuned by `config`
+    pub fn with_config(config: &TransportConfig) -> Result<Self, AgentError> {
+        let mut builder = reqwest::Client::builder()
+            .pool_idle_timeout(config.pool_idle_timeout)
+            .pool_max_idle_per_host(config.pool_max_idle_per_host)
+            .tcp_keepalive(config.tcp_keepalive)
+            .tcp_nodelay(true)
+            .gzip(config.decompression)
+            .deflate(config.decompression)
+            .brotli(config.decompression);
+        if config.dns_cache {
+            builder = enable_dns_cache(builder)?;
+        }
+        Ok(Self::with_client(builder.build()?).with_redactor(config.redactor.clone()))
+    }
+}
+
+/// Connection settings for `ReqwestTransport::with_config`
+///
+/// A service sending a steady stream of requests to the same provider
+/// benefits from keeping connections warm: every request that reuses a
+/// pooled connection skips the DNS lookup and the TCP and TLS handshakes,
+/// typically a few round trips to the provider. Keep `pool_idle_timeout`
+/// above the usual gap between requests so connections are not dropped in
+/// between them.
+#[derive(Debug, Clone)]
+pub struct TransportConfig {
+    /// How long an unused pooled connection is kept open; `None` keeps it forever
+    pub pool_idle_timeout: Option<Duration>,
+    /// Idle connections kept per host
+    pub pool_max_idle_per_host: usize,
+    /// TCP keepalive interval, so idle pooled connections are not silently dropped
+    pub tcp_keepalive: Option<Duration>,
+    /// Resolve hosts with the caching hickory resolver instead of the system
+    /// resolver on every new connection
+    ///
+    /// Needs the `dns-cache` feature; without it `with_config` fails.
+    pub dns_cache: bool,
+    /// Ask for gzip, deflate and brotli responses and decode them; on by
+    /// default
+    ///
+    /// Turn off for proxies that mangle compressed bodies; responses are
+    /// then requested and returned uncompressed.
+    pub decompression: bool,
+    /// What is masked when requests and responses are logged
+    pub redactor: HeaderRedactor,
+}
+
+impl Default for TransportConfig {
+    /// reqwest's defaults plus a 60s keepalive
+    fn default() -> Self {
+        Self {
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            pool_max_idle_per_host: usize::MAX,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            dns_cache: false,
+            decompression: true,
+            redactor: HeaderRedactor::default(),
+        }
+    }
+}
+
+#[cfg(feature = "dns-cache")]
+fn enable_dns_cache(builder: reqwest::ClientBuilder) -> Result<reqwest::ClientBuilder, AgentError> {
+    Ok(builder.hickory_dns(true))
+}
+
+#[cfg(not(feature = "dns-cache"))]
+fn enable_dns_cache(
+    _builder: reqwest::ClientBuilder,
+) -> Result<reqwest::ClientBuilder, AgentError> {
+    Err(AgentError::ApiError(
+        "DNS caching needs agent-core's dns-cache feature".to_string(),
+    ))
+}
+
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AgentError> {
+        let response = self
+            .client
+            .request(request.method, &request.url)
+            .headers(request.headers)
+            .body(request.body)
+            .send()
+            .await?;
+
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?.to_vec();
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    fn redactor(&self) -> &HeaderRedactor {
+        &self.redactor
+    }
+}
+
+/// Test double that replays canned responses and records requests
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: Mutex<VecDeque<HttpResponse>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a response; responses are returned in the order they were queued
+    pub fn with_response(self, response: HttpResponse) -> Self {
+        self.responses.lock().unwrap().push_back(response);
+        self
+    }
+
+    /// Queue a JSON response
+    pub fn with_json(self, status: u16, body: serde_json::Value) -> Self {
+        self.with_response(HttpResponse::json(status, &body))
+    }
+
+    /// Requests sent so far
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl HttpTransport for MockTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AgentError> {
+        self.requests.lock().unwrap().push(request);
+        self.responses.lock().unwrap().pop_front().ok_or_else(|| {
+            AgentError::ApiError("mock transport has no response queued".to_string())
+        })
+    }
+}
+
+/// POST a JSON body and decode the JSON response
+///
+/// Headers and bodies are logged through the transport's `redactor`, and
+/// non-success statuses become `AgentError::ApiError` carrying the response
+/// body. Bodies that are not UTF-8 or not JSON become
+/// `AgentError::ParseError`.
+pub(crate) async fn post_json(
+    transport: &dyn HttpTransport,
+    url: &str,
+    headers: HeaderMap,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, AgentError> {
+    let body = post_text(transport, url, headers, body).await?;
+    serde_json::from_str(&body).map_err(|e| AgentError::ParseError(e.to_string()))
+}
+
+/// `post_json`, returning the response body as text, e.g. an event stream
+pub(crate) async fn post_text(
+    transport: &dyn HttpTransport,
+    url: &str,
+    headers: HeaderMap,
+    body: &serde_json::Value,
+) -> Result<String, AgentError> {
+    let redactor = transport.redactor();
+    tracing::debug!(
+        url,
+        headers = ?redactor.redact(&headers),
+        body = %redactor.redact_body(body),
+        "sending provider request"
+    );
+
+    let mut headers = headers;
+    headers.insert(
+        reqwest::header::CONTENT_TYPE,
+        reqwest::header::HeaderValue::from_static("application/json"),
+    );
+    let response = transport
+        .send(HttpRequest {
+            method: Method::POST,
+            url: url.to_string(),
+            headers,
+            body: body.to_string().into_bytes(),
+        })
+        .await?;
+
+    if response.status == 401 || response.status == 403 {
+        return Err(AgentError::Unauthorized(format!(
+            "{} returned status {}: {}",
+            url,
+            response.status,
+            error_message(&response.body)
+        )));
+    }
+    if !(200..300).contains(&response.status) {
+        return Err(AgentError::ApiError(format!(
+            "{} returned status {}: {}",
+            url,
+            response.status,
+            String::from_utf8_lossy(&response.body)
+        )));
+    }
+
+    let text = String::from_utf8(response.body).map_err(|_| {
+        let content_type = response
+            .headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("none");
+        AgentError::ParseError(format!(
+            "response was not valid UTF-8 (content-type: {})",
+            content_type
+        ))
+    })?;
+    tracing::debug!(url, body = %loggable_body(redactor, &text), "provider response");
+    Ok(text)
+}
+
+/// Response body as logged: JSON and event stream payloads go through
+/// `redactor`, other text is logged as is
+fn loggable_body(redactor: &HeaderRedactor, text: &str) -> String {
+    if let Ok(json) = serde_json::from_str(text) {
+        return redactor.redact_body(&json).to_string();
+    }
+    text.lines()
+        .map(|line| {
+            let event = line
+                .strip_prefix("data:")
+                .and_then(|data| serde_json::from_str(data.trim_start()).ok());
+            match event {
+                Some(json) => format!("data: {}", redactor.redact_body(&json)),
+                None => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Provider error message from an error body, or the raw body
+///
+/// OpenAI and Anthropic both report errors as `{"error": {"message": ...}}`.
+fn error_message(body: &[u8]) -> String {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| json["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    #[test]
+    fn test_transport_config() {
+        assert!(ReqwestTransport::with_config(&TransportConfig::default()).is_ok());
+
+        let dns_cache = TransportConfig {
+            dns_cache: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            ReqwestTransport::with_config(&dns_cache).is_ok(),
+            cfg!(feature = "dns-cache")
+        );
+    }
+
+    #[test]
+    fn test_logged_bodies_are_redacted() {
+        let call =
+            json!({"function": {"name": "login", "arguments": "{\"password\":\"hunter2\"}"}});
+        let response = json!({"choices": [{"message": {"content": "Hi", "tool_calls": [call]}}]});
+        // Bodies are logged in full unless masking is opted into
+        let logged = loggable_body(&HeaderRedactor::default(), &response.to_string());
+        assert!(logged.contains("hunter2"));
+        let redactor = HeaderRedactor::default().with_tool_arguments();
+        let logged = loggable_body(&redactor, &response.to_string());
+        assert!(!logged.contains("hunter2"));
+        assert!(logged.contains("Hi"));
+
+        let stream = format!(
+            "data: {}\n\ndata: [DONE]\n",
+            json!({"choices": [{"delta": {"tool_calls": [call]}}]})
+        );
+        let logged = loggable_body(&redactor, &stream);
+        assert!(!logged.contains("hunter2"));
+        assert!(logged.ends_with("data: [DONE]"));
+
+        let prompts = HeaderRedactor::default().with_body_path("messages.content");
+        let request = json!({"messages": [{"role": "user", "content": "my secret plan"}]});
+        assert!(!prompts.redact_body(&request).to_string().contains("secret"));
+    }
+
+    #[test]
+    fn test_transport_config_redactor_is_used() {
+        let config = TransportConfig {
+            redactor: HeaderRedactor::new().with_allowed("authorization"),
+            ..Default::default()
+        };
+        let transport = ReqwestTransport::with_config(&config).unwrap();
+        assert!(!transport.redactor().is_redacted("authorization"));
+        assert!(ReqwestTransport::new()
+            .redactor()
+            .is_redacted("authorization"));
+        assert!(MockTransport::new().redactor().is_redacted("authorization"));
+    }
+
+    /// `data` as a gzip member holding one uncompressed deflate block
+    fn gzip_stored(data: &[u8]) -> Vec<u8> {
+        let crc = !data.iter().fold(!0u32, |crc, &byte| {
+            (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+                (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+            })
+        });
+        let len = data.len() as u16;
+        let mut gzip = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff, 1];
+        gzip.extend(len.to_le_bytes());
+        gzip.extend((!len).to_le_bytes());
+        gzip.extend(data);
+        gzip.extend(crc.to_le_bytes());
+        gzip.extend((data.len() as u32).to_le_bytes());
+        gzip
+    }
+
+    #[tokio::test]
+    async fn test_gzip_responses_are_decoded() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4096];
+            let read = socket.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+            let accepted = request
+                .lines()
+                .find_map(|line| line.strip_prefix("accept-encoding: "))
+                .unwrap_or_default();
+            assert!(["gzip", "deflate", "br"]
+                .iter()
+                .all(|encoding| accepted.contains(encoding)));
+
+            let body = gzip_stored(br#"{"ok": true}"#);
+            let head = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                 content-encoding: gzip\r\ncontent-length: {}\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&body).await.unwrap();
+        });
+
+        let transport = ReqwestTransport::with_config(&TransportConfig::default()).unwrap();
+        let body = post_json(&transport, &url, HeaderMap::new(), &json!({}))
+            .await
+            .unwrap();
+        assert_eq!(body, json!({"ok": true}));
+    }
+
+    #[tokio::test]
+    async fn test_post_json_rejects_non_utf8_body() {
+        let mut response = HttpResponse::json(200, &json!({}));
+        response.body = vec![b'{', 0xff, 0xfe, b'}'];
+        let transport = MockTransport::new().with_response(response);
+
+        let error = post_json(&transport, "http://test/ok", HeaderMap::new(), &json!({}))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Parse error: response was not valid UTF-8 (content-type: application/json)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_json_through_mock_transport() {
+        let transport = MockTransport::new()
+            .with_json(200, json!({"ok": true}))
+            .with_json(500, json!({"error": "boom"}));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer key"));
+        let body = json!({"hello": "world"});
+
+        let response = post_json(&transport, "http://test/ok", headers.clone(), &body)
+            .await
+            .unwrap();
+        assert_eq!(response, json!({"ok": true}));
+
+        let error = post_json(&transport, "http://test/fail", headers, &body)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("status 500"));
+
+        let unauthorized = MockTransport::new().with_json(
+            401,
+            json!({"error": {"type": "invalid_request_error", "message": "Incorrect API key"}}),
+        );
+        match post_json(&unauthorized, "http://test/ok", HeaderMap::new(), &body).await {
+            Err(AgentError::Unauthorized(message)) => {
+                assert!(message.ends_with("status 401: Incorrect API key"))
+            }
+            other => panic!("expected Unauthorized, got {:?}", other),
+        }
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, Method::POST);
+        assert_eq!(requests[0].headers["content-type"], "application/json");
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&requests[0].body).unwrap(),
+            body
+        );
+    }
+}